@@ -0,0 +1,105 @@
+use crate::{cpulist, cpumask::CpuMask, topology::online_cpus, Error};
+use libc::{sched_getaffinity, sched_getcpu, sched_setaffinity, CPU_SETSIZE};
+use std::{fs, io, path::Path};
+
+//> NR_CPUS of a CONFIG_MAXSMP kernel, the bound when sysfs is unavailable
+const MAX_CPUS: usize = 8192;
+
+//> number of cpu ids the running kernel can ever use, from the possible mask
+fn possible_cpus() -> Option<usize> {
+    let list = fs::read_to_string("/sys/devices/system/cpu/possible").ok()?;
+    cpulist::parse(&list)?.into_iter().max().map(|max| max + 1)
+}
+
+//> cpu ids at or above this are invalid on the running kernel
+pub fn cpu_limit() -> usize {
+    possible_cpus().unwrap_or(MAX_CPUS)
+}
+
+//> masks grow with the cpu id, so ids no kernel can have never allocate one
+pub fn check_cpu(core_id: usize) -> Result<(), Error> {
+    let limit = cpu_limit();
+    if core_id >= limit {
+        return Err(Error::InvalidCpu {
+            cpu: core_id,
+            reason: format!("the kernel supports cpu ids below {limit}"),
+        });
+    }
+    Ok(())
+}
+
+//> turn an affinity errno into the reason the kernel most likely had
+fn classify(thread: &'static str, cpu: usize, source: io::Error) -> Error {
+    match source.raw_os_error() {
+        Some(libc::EPERM) => Error::PermissionDenied { thread, cpu },
+        //> EINVAL means the mask holds no cpu this task may run on
+        Some(libc::EINVAL) => {
+            if !Path::new(&format!("/sys/devices/system/cpu/cpu{cpu}")).exists() {
+                Error::InvalidCpu {
+                    cpu,
+                    reason: "no such cpu on this machine".into(),
+                }
+            } else if !online_cpus().contains(&cpu) {
+                Error::CpuOffline { cpu }
+            } else {
+                Error::CpuNotAllowed { thread, cpu }
+            }
+        }
+        _ => Error::Affinity {
+            thread,
+            cpu,
+            source,
+        },
+    }
+}
+
+//> pid 0 pins the calling thread, `thread` only names it in errors
+pub fn set_current_thread_affinity(thread: &'static str, core_id: usize) -> Result<(), Error> {
+    check_cpu(core_id)?;
+    let mask = CpuMask::single(core_id);
+    let ret = unsafe { sched_setaffinity(0, mask.size_bytes(), mask.as_ptr()) };
+    if ret != 0 {
+        return Err(classify(thread, core_id, io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+//> cpus the calling thread may run on. Read before pinning anything, this is
+//> the mask the process inherited
+pub fn process_affinity() -> io::Result<Vec<usize>> {
+    //> EINVAL while the mask is smaller than the kernel's, double and retry
+    let mut capacity = possible_cpus().unwrap_or(CPU_SETSIZE as usize);
+    loop {
+        let mut mask = CpuMask::with_capacity(capacity);
+        let ret = unsafe { sched_getaffinity(0, mask.size_bytes(), mask.as_mut_ptr()) };
+        if ret == 0 {
+            return Ok(mask.cpus().collect());
+        }
+        let e = io::Error::last_os_error();
+        if e.raw_os_error() != Some(libc::EINVAL) || capacity >= MAX_CPUS * 8 {
+            return Err(e);
+        }
+        capacity *= 2;
+    }
+}
+
+//> sched_setaffinity migrates before returning, so anything else is a bug or a race
+//> with an external tool changing our affinity
+pub fn verify_current_cpu(thread: &'static str, core_id: usize) -> Result<(), Error> {
+    let actual = unsafe { sched_getcpu() };
+    if actual < 0 {
+        return Err(Error::Affinity {
+            thread,
+            cpu: core_id,
+            source: io::Error::last_os_error(),
+        });
+    }
+    if actual as usize != core_id {
+        return Err(Error::NotOnCpu {
+            thread,
+            cpu: core_id,
+            actual: actual as usize,
+        });
+    }
+    Ok(())
+}
@@ -0,0 +1,78 @@
+use std::env;
+
+//> options that take a value
+const OPTIONS: &[&str] = &[
+    "clock",
+    "placement",
+    "ordering",
+    "write",
+    "format",
+    "stop",
+    "warmup",
+    "runs",
+    "pause-ms",
+    "precision",
+    "batch",
+    "budget",
+    "stall",
+    "cpus",
+    "color",
+    "svg",
+    "threshold",
+    "mem-node",
+];
+//> options that take no value
+const FLAGS: &[&str] = &["directional", "boundaries"];
+
+//> positional arguments plus `--name value` options in any order
+pub struct Args {
+    pub program: String,
+    pub positional: Vec<String>,
+    options: Vec<(String, Option<String>)>,
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        let mut args = env::args();
+        let program = args.next().unwrap_or_else(|| "coreping".into());
+        let mut positional = Vec::new();
+        let mut options = Vec::new();
+
+        while let Some(arg) = args.next() {
+            let Some(name) = arg.strip_prefix("--") else {
+                positional.push(arg);
+                continue;
+            };
+            if FLAGS.contains(&name) {
+                options.push((name.to_string(), None));
+                continue;
+            }
+            if !OPTIONS.contains(&name) {
+                return Err(format!("unknown option --{name}"));
+            }
+            let value = args
+                .next()
+                .ok_or_else(|| format!("option --{name} needs a value"))?;
+            options.push((name.to_string(), Some(value)));
+        }
+
+        Ok(Self {
+            program,
+            positional,
+            options,
+        })
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.options.iter().any(|(n, _)| n == name)
+    }
+
+    //> last occurrence wins
+    pub fn option(&self, name: &str) -> Option<&str> {
+        self.options
+            .iter()
+            .rev()
+            .find(|(n, _)| n == name)
+            .and_then(|(_, v)| v.as_deref())
+    }
+}
@@ -0,0 +1,154 @@
+use std::{
+    fs,
+    time::{Duration, Instant},
+};
+
+const CPUINFO: &str = "/proc/cpuinfo";
+const CALIBRATION: Duration = Duration::from_millis(50);
+
+//> time source for the hot loops, read as raw ticks
+#[derive(Clone, Copy)]
+pub enum Clock {
+    //> cycles of an invariant tsc calibrated against clock_monotonic
+    Tsc { ticks_per_ns: f64 },
+    //> nanoseconds of clock_monotonic through the vdso
+    Monotonic { base: Instant },
+}
+
+//> tsc keeps ticking at a fixed rate across p-states and deep c-states
+pub fn tsc_is_invariant() -> bool {
+    let Ok(cpuinfo) = fs::read_to_string(CPUINFO) else {
+        return false;
+    };
+    cpuinfo
+        .lines()
+        .find(|line| line.starts_with("flags"))
+        .map(|line| {
+            let flags: Vec<&str> = line.split_whitespace().collect();
+            flags.contains(&"constant_tsc") && flags.contains(&"nonstop_tsc")
+        })
+        .unwrap_or(false)
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+fn rdtsc() -> u64 {
+    use std::arch::x86_64::{_mm_lfence, _rdtsc};
+    //> lfence on both sides keeps rdtsc from drifting across the loads around it
+    unsafe {
+        _mm_lfence();
+        let ticks = _rdtsc();
+        _mm_lfence();
+        ticks
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+fn rdtscp() -> u64 {
+    use std::arch::x86_64::{__rdtscp, _mm_lfence};
+    let mut aux = 0;
+    unsafe {
+        let ticks = __rdtscp(&mut aux);
+        _mm_lfence();
+        ticks
+    }
+}
+
+impl Clock {
+    pub fn monotonic() -> Self {
+        Clock::Monotonic {
+            base: Instant::now(),
+        }
+    }
+
+    #[cfg(target_arch = "x86_64")]
+    pub fn tsc() -> Result<Self, String> {
+        if !tsc_is_invariant() {
+            return Err("tsc is not invariant (constant_tsc/nonstop_tsc missing)".into());
+        }
+
+        //> count tsc ticks across a busy wait on clock_monotonic
+        let start = Instant::now();
+        let tsc_start = rdtscp();
+        while start.elapsed() < CALIBRATION {}
+        let tsc_end = rdtscp();
+        let elapsed = start.elapsed();
+
+        Ok(Clock::Tsc {
+            ticks_per_ns: (tsc_end - tsc_start) as f64 / elapsed.as_nanos() as f64,
+        })
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    pub fn tsc() -> Result<Self, String> {
+        Err("tsc is only available on x86_64".into())
+    }
+
+    //> invariant tsc when available, clock_monotonic otherwise
+    pub fn best() -> Self {
+        Self::tsc().unwrap_or_else(|_| Self::monotonic())
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "tsc" => Self::tsc(),
+            "monotonic" => Ok(Self::monotonic()),
+            _ => Err(format!("unknown clock {name:?}, expected tsc or monotonic")),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Clock::Tsc { .. } => "tsc",
+            Clock::Monotonic { .. } => "monotonic",
+        }
+    }
+
+    #[inline(always)]
+    pub fn now(self) -> u64 {
+        match self {
+            #[cfg(target_arch = "x86_64")]
+            Clock::Tsc { .. } => rdtsc(),
+            #[cfg(not(target_arch = "x86_64"))]
+            Clock::Tsc { .. } => unreachable!(),
+            Clock::Monotonic { base } => base.elapsed().as_nanos() as u64,
+        }
+    }
+
+    //> the variants are public, a tsc clock built by hand has to be usable
+    pub fn validate(self) -> Result<(), String> {
+        match self {
+            Clock::Tsc { .. } if !cfg!(target_arch = "x86_64") => {
+                Err("the tsc clock is only available on x86_64".into())
+            }
+            Clock::Tsc { ticks_per_ns } if !(ticks_per_ns.is_finite() && ticks_per_ns > 0.0) => {
+                Err(format!("invalid tsc rate of {ticks_per_ns} ticks per ns"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn is_cycles(self) -> bool {
+        matches!(self, Clock::Tsc { .. })
+    }
+
+    pub fn ticks_per_ns(self) -> f64 {
+        match self {
+            Clock::Tsc { ticks_per_ns } => ticks_per_ns,
+            Clock::Monotonic { .. } => 1.0,
+        }
+    }
+
+    pub fn to_ns(self, ticks: u64) -> f64 {
+        ticks as f64 / self.ticks_per_ns()
+    }
+
+    pub fn to_duration(self, ticks: u64) -> Duration {
+        Duration::from_nanos(self.to_ns(ticks) as u64)
+    }
+
+    pub fn ticks_in(self, duration: Duration) -> u64 {
+        (duration.as_nanos() as f64 * self.ticks_per_ns()) as u64
+    }
+}
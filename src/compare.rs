@@ -0,0 +1,437 @@
+use crate::{
+    report::CSV_COLUMNS,
+    stats::{t_999, welch_df, welch_t, Z_999},
+};
+use std::collections::BTreeMap;
+
+//> round trip distribution of one pair as saved by `--format csv`
+pub struct Sample {
+    pub src: usize,
+    pub dst: usize,
+    pub relation: String,
+    pub iterations: f64,
+    pub mean_ns: f64,
+    pub stddev_ns: f64,
+}
+
+//> read the rows of a `--format csv` result, columns are found by name so
+//> files with fewer or more trailing columns still load. Rows without a
+//> complete distribution, e.g. pairs that never answered, are skipped, and so
+//> are repeated headers, so the output of several runs can be concatenated
+pub fn parse_csv(text: &str) -> Result<Vec<Sample>, String> {
+    let mut lines = text.lines();
+    let first = lines.next().unwrap_or_default();
+    let header: Vec<&str> = first.split(',').collect();
+    if header.first() != CSV_COLUMNS.first() {
+        return Err("not a coreping csv result, save one with --format csv".into());
+    }
+    let column = |name: &str| {
+        header
+            .iter()
+            .position(|&c| c == name)
+            .ok_or_else(|| format!("csv lacks the {name} column"))
+    };
+    let (src, dst, relation) = (column("src_cpu")?, column("dst_cpu")?, column("relation")?);
+    let (iterations, mean, stddev) = (
+        column("iterations")?,
+        column("mean_ns")?,
+        column("stddev_ns")?,
+    );
+
+    let mut samples = Vec::new();
+    for (line, row) in lines
+        .enumerate()
+        .filter(|&(_, row)| !row.is_empty() && row != first)
+    {
+        let fields: Vec<&str> = row.split(',').collect();
+        let invalid = || format!("malformed csv row {}: {row:?}", line + 2);
+        let get = |i: usize| fields.get(i).copied().ok_or_else(invalid);
+        let number = |i: usize| -> Result<Option<f64>, String> {
+            match get(i)? {
+                "" => Ok(None),
+                value => value.parse().map(Some).map_err(|_| invalid()),
+            }
+        };
+        let (Some(n), Some(mean_ns), Some(stddev_ns)) =
+            (number(iterations)?, number(mean)?, number(stddev)?)
+        else {
+            continue;
+        };
+        samples.push(Sample {
+            src: get(src)?.parse().map_err(|_| invalid())?,
+            dst: get(dst)?.parse().map_err(|_| invalid())?,
+            relation: get(relation)?.to_string(),
+            iterations: n,
+            mean_ns,
+            stddev_ns,
+        });
+    }
+    Ok(samples)
+}
+
+//> count, mean and variance of round trips, combinable across rows
+#[derive(Clone, Copy, Default, Debug)]
+struct Moments {
+    n: f64,
+    mean: f64,
+    var: f64,
+}
+
+impl Moments {
+    fn of(sample: &Sample) -> Self {
+        Self {
+            n: sample.iterations,
+            mean: sample.mean_ns,
+            var: sample.stddev_ns * sample.stddev_ns,
+        }
+    }
+
+    //> exact pooled moments, as if the samples had been recorded together
+    fn merge(self, other: Self) -> Self {
+        let n = self.n + other.n;
+        if n <= 1.0 {
+            return if self.n > 0.0 { self } else { other };
+        }
+        let mean = (self.n * self.mean + other.n * other.mean) / n;
+        let squares = |m: Self| (m.n - 1.0).max(0.0) * m.var + m.n * (m.mean - mean).powi(2);
+        Self {
+            n,
+            mean,
+            var: (squares(self) + squares(other)) / (n - 1.0),
+        }
+    }
+}
+
+//> what welch's t treats as one observation
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Basis {
+    //> the mean of each `--runs` row, so run to run variance is included
+    Runs,
+    //> single round trips. They are autocorrelated within a run and miss the
+    //> variance between runs, so t overstates significance
+    RoundTrips,
+}
+
+impl Basis {
+    pub fn label(self) -> &'static str {
+        match self {
+            Basis::Runs => "per run",
+            Basis::RoundTrips => "per sample",
+        }
+    }
+}
+
+//> the rows of one pair, or of every pair of a topology level
+#[derive(Clone, Copy)]
+struct Pooled {
+    round_trips: Moments,
+    //> moments of the per row means
+    runs: Moments,
+    //> fewest rows any pooled pair had
+    min_runs: f64,
+}
+
+impl Pooled {
+    fn of(sample: &Sample) -> Self {
+        Self {
+            round_trips: Moments::of(sample),
+            runs: Moments {
+                n: 1.0,
+                mean: sample.mean_ns,
+                var: 0.0,
+            },
+            min_runs: 1.0,
+        }
+    }
+
+    //> another row of the same pair
+    fn add_run(self, other: Self) -> Self {
+        Self {
+            round_trips: self.round_trips.merge(other.round_trips),
+            runs: self.runs.merge(other.runs),
+            min_runs: self.min_runs + other.min_runs,
+        }
+    }
+
+    //> another pair of the same level
+    fn add_pair(self, other: Self) -> Self {
+        Self {
+            min_runs: self.min_runs.min(other.min_runs),
+            ..self.add_run(other)
+        }
+    }
+}
+
+//> change of the mean round trip from baseline to current
+pub struct Delta {
+    pub baseline_ns: f64,
+    pub current_ns: f64,
+    //> relative, 0.1 is 10% slower
+    pub change: f64,
+    pub t: f64,
+    pub basis: Basis,
+    //> welch's t at p < 0.001
+    pub significant: bool,
+}
+
+impl Delta {
+    //> across runs when both sides have at least two of every pair, across
+    //> round trips otherwise
+    fn between(baseline: Pooled, current: Pooled) -> Self {
+        let runs = baseline.min_runs >= 2.0 && current.min_runs >= 2.0;
+        let (basis, before, after) = if runs {
+            (Basis::Runs, baseline.runs, current.runs)
+        } else {
+            (Basis::RoundTrips, baseline.round_trips, current.round_trips)
+        };
+        let t = welch_t(
+            after.mean,
+            after.var,
+            after.n,
+            before.mean,
+            before.var,
+            before.n,
+        );
+        let critical = match basis {
+            Basis::Runs => t_999(welch_df(after.var, after.n, before.var, before.n)),
+            Basis::RoundTrips => Z_999,
+        };
+        Self {
+            baseline_ns: before.mean,
+            current_ns: after.mean,
+            change: (after.mean - before.mean) / before.mean,
+            t,
+            basis,
+            significant: t.abs() > critical,
+        }
+    }
+
+    //> significantly slower by more than `threshold`
+    pub fn regressed(&self, threshold: f64) -> bool {
+        self.significant && self.change > threshold
+    }
+}
+
+pub struct Comparison {
+    //> ((src, dst), delta) for pairs present in both files
+    pub pairs: Vec<((usize, usize), Delta)>,
+    //> (relation, delta) over all aligned pairs of that topology level, a
+    //> summary only, regressions are judged per pair
+    pub levels: Vec<(String, Delta)>,
+    //> pairs found in only one of the files
+    pub unmatched: Vec<(usize, usize)>,
+}
+
+impl Comparison {
+    pub fn regressions(&self, threshold: f64) -> impl Iterator<Item = &((usize, usize), Delta)> {
+        self.pairs
+            .iter()
+            .filter(move |(_, delta)| delta.regressed(threshold))
+    }
+}
+
+//> rows of the same pair, e.g. from --runs, are pooled first
+fn by_pair(samples: &[Sample]) -> BTreeMap<(usize, usize), (Pooled, &str)> {
+    let mut pairs: BTreeMap<(usize, usize), (Pooled, &str)> = BTreeMap::new();
+    for sample in samples {
+        let row = Pooled::of(sample);
+        pairs
+            .entry((sample.src, sample.dst))
+            .and_modify(|(pooled, _)| *pooled = pooled.add_run(row))
+            .or_insert((row, &sample.relation));
+    }
+    pairs
+}
+
+//> align two results by cpu pair and test every pair and topology level
+pub fn compare(baseline: &[Sample], current: &[Sample]) -> Comparison {
+    let baseline = by_pair(baseline);
+    let current = by_pair(current);
+
+    let mut pairs = Vec::new();
+    let mut levels: BTreeMap<&str, (Pooled, Pooled)> = BTreeMap::new();
+    for (pair, &(before, relation)) in &baseline {
+        let Some(&(after, _)) = current.get(pair) else {
+            continue;
+        };
+        pairs.push((*pair, Delta::between(before, after)));
+        levels
+            .entry(relation)
+            .and_modify(|level| *level = (level.0.add_pair(before), level.1.add_pair(after)))
+            .or_insert((before, after));
+    }
+
+    let unmatched = baseline
+        .keys()
+        .filter(|pair| !current.contains_key(pair))
+        .chain(current.keys().filter(|pair| !baseline.contains_key(pair)))
+        .copied()
+        .collect();
+
+    Comparison {
+        pairs,
+        levels: levels
+            .into_iter()
+            .map(|(relation, (before, after))| {
+                (relation.to_string(), Delta::between(before, after))
+            })
+            .collect(),
+        unmatched,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HEADER: &str = "src_cpu,dst_cpu,relation,numa_distance,iterations,ns_per_op,mean_ns,median_ns,p99_ns,stddev_ns";
+
+    fn csv(rows: &[&str]) -> String {
+        [HEADER]
+            .iter()
+            .chain(rows)
+            .copied()
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn moments(values: &[f64]) -> Moments {
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let var = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        Moments { n, mean, var }
+    }
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() <= 1e-9 * a.abs().max(1.0)
+    }
+
+    #[test]
+    fn parses_rows_by_column_name() {
+        let samples = parse_csv(&csv(&["0,1,smt,10,1000,20,40.5,40,60,3.5"])).unwrap();
+        assert_eq!(samples.len(), 1);
+        let s = &samples[0];
+        assert_eq!((s.src, s.dst, s.relation.as_str()), (0, 1, "smt"));
+        assert_eq!((s.iterations, s.mean_ns, s.stddev_ns), (1000.0, 40.5, 3.5));
+    }
+
+    #[test]
+    fn rejects_foreign_and_incomplete_headers() {
+        assert!(parse_csv("a,b,c\n1,2,3").is_err());
+        assert!(parse_csv("").is_err());
+        let error = parse_csv("src_cpu,dst_cpu,relation,iterations,stddev_ns\n0,1,smt,5,1")
+            .err()
+            .unwrap();
+        assert!(error.contains("mean_ns"), "{error}");
+    }
+
+    #[test]
+    fn skips_rows_with_empty_fields() {
+        let samples = parse_csv(&csv(&[
+            "0,1,smt,10,0,,,,,",
+            "0,2,l3,10,1000,20,40,40,60,",
+            "",
+            "0,3,l3,10,1000,20,41,40,60,2",
+        ]))
+        .unwrap();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].dst, 3);
+    }
+
+    #[test]
+    fn rejects_short_and_malformed_rows() {
+        let error = parse_csv(&csv(&["0,1,smt,10,1000,20,40"])).err().unwrap();
+        assert!(error.contains("row 2"), "{error}");
+        assert!(parse_csv(&csv(&["x,1,smt,10,1000,20,40,40,60,2"])).is_err());
+        assert!(parse_csv(&csv(&["0,1,smt,10,many,20,40,40,60,2"])).is_err());
+    }
+
+    #[test]
+    fn skips_repeated_headers_of_concatenated_runs() {
+        let text = [
+            csv(&["0,1,smt,10,1000,20,40,40,60,2"]),
+            csv(&["0,1,smt,10,1000,20,42,40,60,2"]),
+        ]
+        .join("\n");
+        assert_eq!(parse_csv(&text).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn merge_matches_moments_of_the_union() {
+        let (a, b) = ([3.0, 5.0, 4.0, 10.0], [7.0, 1.0, 2.5]);
+        let merged = moments(&a).merge(moments(&b));
+        let union = moments(&[a.as_slice(), b.as_slice()].concat());
+        assert_eq!(merged.n, union.n);
+        assert!(close(merged.mean, union.mean));
+        assert!(close(merged.var, union.var));
+    }
+
+    #[test]
+    fn merge_with_empty_or_single_samples() {
+        let a = moments(&[1.0, 2.0, 3.0]);
+        let merged = Moments::default().merge(a);
+        assert_eq!((merged.n, merged.mean, merged.var), (a.n, a.mean, a.var));
+        let single = |v| Moments {
+            n: 1.0,
+            mean: v,
+            var: 0.0,
+        };
+        let pair = single(2.0).merge(single(4.0));
+        assert_eq!((pair.n, pair.mean, pair.var), (2.0, 3.0, 2.0));
+    }
+
+    fn sample(src: usize, iterations: f64, mean_ns: f64, stddev_ns: f64) -> Sample {
+        Sample {
+            src,
+            dst: src + 1,
+            relation: "l3".into(),
+            iterations,
+            mean_ns,
+            stddev_ns,
+        }
+    }
+
+    #[test]
+    fn single_rows_are_tested_per_sample() {
+        let comparison = compare(&[sample(0, 1e6, 100.0, 5.0)], &[sample(0, 1e6, 110.0, 5.0)]);
+        let (_, delta) = &comparison.pairs[0];
+        assert_eq!(delta.basis, Basis::RoundTrips);
+        assert!(delta.significant);
+        assert!(delta.regressed(0.05));
+    }
+
+    #[test]
+    fn repeated_rows_are_tested_across_runs() {
+        let runs = |means: &[f64]| -> Vec<Sample> {
+            means.iter().map(|&m| sample(0, 1e6, m, 5.0)).collect()
+        };
+        //> 10% slower on average but the runs scatter as much
+        let comparison = compare(&runs(&[90.0, 110.0, 100.0]), &runs(&[100.0, 120.0, 110.0]));
+        let (_, delta) = &comparison.pairs[0];
+        assert_eq!(delta.basis, Basis::Runs);
+        assert!(close(delta.change, 0.1));
+        assert!(!delta.significant);
+        assert_eq!(comparison.regressions(0.05).count(), 0);
+
+        let comparison = compare(&runs(&[100.0, 100.5, 99.5]), &runs(&[130.0, 130.5, 129.5]));
+        assert!(comparison.pairs[0].1.regressed(0.05));
+    }
+
+    #[test]
+    fn levels_fall_back_to_samples_when_a_pair_has_one_run() {
+        let baseline = [
+            sample(0, 1e6, 100.0, 5.0),
+            sample(0, 1e6, 101.0, 5.0),
+            sample(2, 1e6, 100.0, 5.0),
+        ];
+        let current = [
+            sample(0, 1e6, 100.0, 5.0),
+            sample(0, 1e6, 101.0, 5.0),
+            sample(2, 1e6, 100.0, 5.0),
+        ];
+        let comparison = compare(&baseline, &current);
+        assert_eq!(comparison.pairs[0].1.basis, Basis::Runs);
+        assert_eq!(comparison.pairs[1].1.basis, Basis::RoundTrips);
+        assert_eq!(comparison.levels[0].1.basis, Basis::RoundTrips);
+    }
+}
@@ -0,0 +1,76 @@
+//> parse a kernel cpulist such as "0-3,8,10-11", ranges may carry a stride
+//> as taskset accepts it, "0-31:2" for every other cpu
+pub fn parse(list: &str) -> Option<Vec<usize>> {
+    let mut cpus = Vec::new();
+    for part in list.trim().split(',').filter(|p| !p.is_empty()) {
+        cpus.extend(parse_part(part)?);
+    }
+    Some(cpus)
+}
+
+//> one comma separated element: "8", "0-3" or "0-31:2"
+pub fn parse_part(part: &str) -> Option<Vec<usize>> {
+    let (lo, hi, stride) = range(part)?;
+    Some((lo..=hi).step_by(stride).collect())
+}
+
+//> first, last and stride of an element without expanding it, so callers
+//> can bound the ids first. "8" is (8, 8, 1)
+pub fn range(part: &str) -> Option<(usize, usize, usize)> {
+    let (range, stride) = match part.split_once(':') {
+        Some((range, stride)) => (range, stride.parse().ok().filter(|&s: &usize| s > 0)?),
+        None => (part, 1),
+    };
+    match range.split_once('-') {
+        Some((lo, hi)) => {
+            let (lo, hi): (usize, usize) = (lo.parse().ok()?, hi.parse().ok()?);
+            (lo <= hi).then_some((lo, hi, stride))
+        }
+        None if stride == 1 => {
+            let cpu = range.parse().ok()?;
+            Some((cpu, cpu, 1))
+        }
+        None => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kernel_lists() {
+        assert_eq!(parse("0-3,8,10-11\n"), Some(vec![0, 1, 2, 3, 8, 10, 11]));
+        assert_eq!(parse("5"), Some(vec![5]));
+        assert_eq!(parse(""), Some(vec![]));
+        assert_eq!(parse("0,,2"), Some(vec![0, 2]));
+        assert_eq!(parse("0-a"), None);
+    }
+
+    #[test]
+    fn strided_ranges() {
+        assert_eq!(
+            parse_part("0-31:2"),
+            Some((0..=31).step_by(2).collect::<Vec<_>>())
+        );
+        assert_eq!(parse_part("1-7:3"), Some(vec![1, 4, 7]));
+        assert_eq!(parse_part("4-4:2"), Some(vec![4]));
+    }
+
+    #[test]
+    fn ranges_are_not_expanded() {
+        assert_eq!(range("8"), Some((8, 8, 1)));
+        assert_eq!(range("0-2000000000:2"), Some((0, 2_000_000_000, 2)));
+        assert_eq!(range("3-1"), None);
+    }
+
+    #[test]
+    fn rejected_parts() {
+        //> a stride needs a range
+        assert_eq!(parse_part("5:2"), None);
+        assert_eq!(parse_part("3-1"), None);
+        assert_eq!(parse_part("0-7:0"), None);
+        assert_eq!(parse_part("0-7:"), None);
+        assert_eq!(parse_part("-1"), None);
+    }
+}
@@ -0,0 +1,123 @@
+use libc::c_ulong;
+use std::mem;
+
+const BITS: usize = mem::size_of::<c_ulong>() * 8;
+
+//> heap allocated cpu mask in the kernel's layout, an array of unsigned longs
+//> with cpu n at bit n % BITS of word n / BITS. Unlike cpu_set_t it is not
+//> capped at 1024 cpus, the CPU_ALLOC counterpart for the affinity syscalls
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CpuMask {
+    words: Vec<c_ulong>,
+}
+
+impl CpuMask {
+    //> empty mask with room for cpus 0..cpus, never smaller than one word
+    pub fn with_capacity(cpus: usize) -> Self {
+        Self {
+            words: vec![0; cpus.div_ceil(BITS).max(1)],
+        }
+    }
+
+    //> mask holding exactly `cpu`, sized just large enough for it
+    pub fn single(cpu: usize) -> Self {
+        let mut mask = Self::with_capacity(cpu + 1);
+        mask.set(cpu);
+        mask
+    }
+
+    pub fn set(&mut self, cpu: usize) {
+        let word = cpu / BITS;
+        if word >= self.words.len() {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= 1 << (cpu % BITS);
+    }
+
+    pub fn contains(&self, cpu: usize) -> bool {
+        self.words
+            .get(cpu / BITS)
+            .is_some_and(|word| word & (1 << (cpu % BITS)) != 0)
+    }
+
+    //> number of cpu ids the mask can hold
+    pub fn capacity(&self) -> usize {
+        self.words.len() * BITS
+    }
+
+    //> the cpusetsize argument of the affinity syscalls
+    pub fn size_bytes(&self) -> usize {
+        self.words.len() * mem::size_of::<c_ulong>()
+    }
+
+    pub fn cpus(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.capacity()).filter(|&cpu| self.contains(cpu))
+    }
+
+    pub fn as_ptr(&self) -> *const libc::cpu_set_t {
+        self.words.as_ptr().cast()
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut libc::cpu_set_t {
+        self.words.as_mut_ptr().cast()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn single_sets_only_that_bit() {
+        for cpu in [0, 1, 63, 64, 1023, 1024, 1025, 4095, 8191] {
+            let mask = CpuMask::single(cpu);
+            assert!(mask.contains(cpu), "cpu {cpu}");
+            assert_eq!(mask.cpus().collect::<Vec<_>>(), [cpu]);
+            assert!(mask.capacity() > cpu);
+            assert!(mask.capacity() - cpu <= BITS);
+        }
+    }
+
+    #[test]
+    fn large_index_lands_in_kernel_layout() {
+        let mask = CpuMask::single(1500);
+        assert_eq!(
+            mask.size_bytes(),
+            1500usize.div_ceil(BITS) * mem::size_of::<c_ulong>()
+        );
+        let word = mask.words[1500 / BITS];
+        assert_eq!(word, 1 << (1500 % BITS));
+        assert!(mask.words[..1500 / BITS].iter().all(|&w| w == 0));
+    }
+
+    #[test]
+    fn set_grows_past_capacity() {
+        let mut mask = CpuMask::with_capacity(8);
+        assert_eq!(mask.capacity(), BITS);
+        mask.set(3);
+        mask.set(2048);
+        assert_eq!(mask.cpus().collect::<Vec<_>>(), [3, 2048]);
+        assert!(!mask.contains(2047));
+        assert!(!mask.contains(100_000));
+    }
+
+    #[test]
+    fn matches_cpu_set_below_1024() {
+        let mut cpu_set: libc::cpu_set_t = unsafe { mem::zeroed() };
+        let mut mask = CpuMask::with_capacity(libc::CPU_SETSIZE as usize);
+        for cpu in [0, 5, 64, 700, 1023] {
+            unsafe { libc::CPU_SET(cpu, &mut cpu_set) };
+            mask.set(cpu);
+        }
+        assert_eq!(mask.size_bytes(), mem::size_of::<libc::cpu_set_t>());
+        let raw =
+            unsafe { std::slice::from_raw_parts(mask.as_ptr().cast::<u8>(), mask.size_bytes()) };
+        let expected = unsafe {
+            std::slice::from_raw_parts(
+                (&cpu_set as *const libc::cpu_set_t).cast::<u8>(),
+                mask.size_bytes(),
+            )
+        };
+        assert_eq!(raw, expected);
+    }
+}
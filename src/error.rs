@@ -0,0 +1,177 @@
+use std::{fmt, io, time::Duration};
+
+#[derive(Debug)]
+pub enum Error {
+    //> a command line argument could not be parsed
+    InvalidArgument(String),
+    //> the configuration can never be measured
+    InvalidConfig(String),
+    //> the cpu id does not exist on this machine or is beyond what the kernel supports
+    InvalidCpu {
+        cpu: usize,
+        reason: String,
+    },
+    //> the cpu exists but is offline
+    CpuOffline {
+        cpu: usize,
+    },
+    //> the cpu is online but outside the cpuset this process may run on
+    CpuNotAllowed {
+        thread: &'static str,
+        cpu: usize,
+    },
+    //> the cpu is online but the cgroup cpuset or the inherited affinity mask excludes it
+    CpuUnusable {
+        cpu: usize,
+        reason: String,
+    },
+    //> changing affinity needs privileges the process does not have
+    PermissionDenied {
+        thread: &'static str,
+        cpu: usize,
+    },
+    //> sched_setaffinity or pthread_setaffinity_np failed for another reason
+    Affinity {
+        thread: &'static str,
+        cpu: usize,
+        source: io::Error,
+    },
+    //> the thread was pinned but sched_getcpu reports another cpu
+    NotOnCpu {
+        thread: &'static str,
+        cpu: usize,
+        actual: usize,
+    },
+    //> not a single round trip was measured before the timeout
+    Timeout {
+        after: Duration,
+    },
+    //> the worker thread panicked instead of returning
+    WorkerPanicked,
+    //> a report file could not be written
+    Output {
+        path: String,
+        source: io::Error,
+    },
+    //> compare found pairs slower than the baseline beyond the threshold
+    Regression {
+        pairs: usize,
+        threshold: f64,
+    },
+    //> SIGINT or SIGTERM arrived, whatever completed was still reported
+    Interrupted {
+        signal: i32,
+    },
+    //> the round trip counters stopped moving before the timeout
+    Stalled {
+        after: Duration,
+    },
+    //> the ping-pong lines could not be mapped
+    Memory {
+        source: io::Error,
+    },
+    //> the ping-pong lines could not be homed on the requested numa node
+    MemoryNode {
+        node: usize,
+        reason: String,
+    },
+}
+
+impl Error {
+    //> distinct process exit code per failure class, stable for scripts
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::InvalidArgument(_) | Error::InvalidConfig(_) => 2,
+            Error::InvalidCpu { .. } => 3,
+            Error::CpuOffline { .. } => 4,
+            Error::CpuNotAllowed { .. } | Error::CpuUnusable { .. } => 5,
+            Error::PermissionDenied { .. } => 6,
+            Error::Affinity { .. } => 7,
+            Error::Timeout { .. } => 8,
+            Error::WorkerPanicked => 9,
+            Error::NotOnCpu { .. } => 10,
+            Error::Stalled { .. } => 11,
+            Error::Output { .. } => 12,
+            Error::Regression { .. } => 13,
+            Error::Memory { .. } | Error::MemoryNode { .. } => 14,
+            //> the shell convention for death by signal
+            Error::Interrupted { signal } => 128 + signal,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::InvalidArgument(reason) => write!(f, "invalid argument: {reason}"),
+            Error::InvalidConfig(reason) => write!(f, "invalid configuration: {reason}"),
+            Error::InvalidCpu { cpu, reason } => write!(f, "invalid cpu {cpu}: {reason}"),
+            Error::CpuOffline { cpu } => write!(
+                f,
+                "cpu {cpu} is offline (echo 1 > /sys/devices/system/cpu/cpu{cpu}/online to bring it up)"
+            ),
+            Error::CpuNotAllowed { thread, cpu } => write!(
+                f,
+                "cannot pin {thread} thread to cpu {cpu}: not in the cpuset this process may use"
+            ),
+            Error::CpuUnusable { cpu, reason } => write!(f, "cpu {cpu} is not usable: {reason}"),
+            Error::PermissionDenied { thread, cpu } => write!(
+                f,
+                "permission denied pinning {thread} thread to cpu {cpu}"
+            ),
+            Error::Affinity {
+                thread,
+                cpu,
+                source,
+            } => write!(
+                f,
+                "failed to set affinity of {thread} thread to cpu {cpu}: {source}"
+            ),
+            Error::NotOnCpu {
+                thread,
+                cpu,
+                actual,
+            } => write!(
+                f,
+                "{thread} thread was pinned to cpu {cpu} but runs on cpu {actual}"
+            ),
+            Error::Timeout { after } => write!(
+                f,
+                "no round trip measured within {:.1} s, the partner never answered or warmup used up the timeout",
+                after.as_secs_f64()
+            ),
+            Error::WorkerPanicked => write!(f, "worker thread panicked"),
+            Error::Output { path, source } => write!(f, "cannot write {path}: {source}"),
+            Error::Regression { pairs, threshold } => write!(
+                f,
+                "{pairs} pair(s) significantly slower than the baseline by more than {:.1}%",
+                threshold * 100.0
+            ),
+            Error::Interrupted { signal } => write!(
+                f,
+                "interrupted by {}, partial results reported",
+                crate::signal::name(*signal)
+            ),
+            Error::Stalled { after } => write!(
+                f,
+                "partner stopped answering for {:.1} s, partial results reported",
+                after.as_secs_f64()
+            ),
+            Error::Memory { source } => write!(f, "cannot map the ping-pong lines: {source}"),
+            Error::MemoryNode { node, reason } => {
+                write!(f, "cannot place the lines on numa node {node}: {reason}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Affinity { source, .. }
+            | Error::Output { source, .. }
+            | Error::Memory { source } => Some(source),
+            _ => None,
+        }
+    }
+}
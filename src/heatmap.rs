@@ -0,0 +1,186 @@
+use crate::{matrix::Matrix, topology::Topology};
+use std::{env, fmt::Write};
+
+//> ansi colour depth of the cells
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Colour {
+    TrueColour,
+    Ansi256,
+}
+
+impl Colour {
+    //> truecolour when the terminal advertises it through COLORTERM
+    pub fn detect() -> Self {
+        match env::var("COLORTERM").as_deref() {
+            Ok("truecolor" | "24bit") => Colour::TrueColour,
+            _ => Colour::Ansi256,
+        }
+    }
+
+    //> background escape for `t` in 0..=1, green through yellow to red
+    fn background(self, t: f64) -> String {
+        let (r, g, b) = gradient(t);
+        match self {
+            Colour::TrueColour => format!("\x1b[48;2;{r};{g};{b}m"),
+            Colour::Ansi256 => {
+                let cube = |c: u8| (c as u32 * 5 + 127) / 255;
+                format!("\x1b[48;5;{}m", 16 + 36 * cube(r) + 6 * cube(g) + cube(b))
+            }
+        }
+    }
+}
+
+const RESET: &str = "\x1b[0m";
+//> swatches in the legend
+const LEGEND_STEPS: usize = 8;
+
+pub(crate) fn gradient(t: f64) -> (u8, u8, u8) {
+    let t = t.clamp(0.0, 1.0);
+    let channel = |v: f64| (v * 255.0).round() as u8;
+    if t < 0.5 {
+        (channel(t * 2.0), 200, 0)
+    } else {
+        (255, channel((1.0 - t) * 2.0 * 200.0 / 255.0), 0)
+    }
+}
+
+//> socket, die, l3 (the ccx on chiplet parts) of a cpu, what boundaries split on
+fn cluster(topology: &Topology, cpu: usize) -> (Option<usize>, Option<usize>, Option<usize>) {
+    match topology.cpu(cpu) {
+        Some(c) => (c.package_id, c.die_id, c.l3_id),
+        None => (None, None, None),
+    }
+}
+
+//> `cpus` sorted by socket, die, l3, core and smt thread, so cpus that share
+//> more of the hierarchy end up next to each other
+pub fn order(topology: &Topology, cpus: &[usize]) -> Vec<usize> {
+    let mut ordered = cpus.to_vec();
+    ordered.sort_by_key(|&cpu| {
+        //> the lowest smt sibling stands for the physical core
+        let core = topology
+            .cpu(cpu)
+            .and_then(|c| c.smt_siblings.iter().min().copied())
+            .unwrap_or(cpu);
+        (cluster(topology, cpu), core, cpu)
+    });
+    ordered
+}
+
+//> positions in `ordered` where a new cluster starts
+fn boundaries(topology: &Topology, ordered: &[usize]) -> Vec<bool> {
+    ordered
+        .iter()
+        .enumerate()
+        .map(|(i, &cpu)| i > 0 && cluster(topology, cpu) != cluster(topology, ordered[i - 1]))
+        .collect()
+}
+
+//> ns per op matrix as coloured cells ordered by topology, with column ids
+//> written vertically above the cells and a legend below. `lines` draws a
+//> rule between clusters
+pub fn render(matrix: &Matrix, topology: &Topology, colour: Colour, lines: bool) -> String {
+    let rows = order(topology, &matrix.rows);
+    let columns = order(topology, &matrix.columns);
+    let row_breaks = boundaries(topology, &rows);
+    let column_breaks = boundaries(topology, &columns);
+    let value = |main_core, worker_core| {
+        matrix
+            .get(main_core, worker_core)
+            .and_then(|m| m.ns_per_op_f64())
+    };
+
+    let values: Vec<f64> = matrix
+        .results
+        .iter()
+        .filter_map(|m| m.ns_per_op_f64())
+        .collect();
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let scale = |ns: f64| {
+        if max > min {
+            (ns - min) / (max - min)
+        } else {
+            0.0
+        }
+    };
+
+    //> two character cells while they fit a wide terminal, one beyond that
+    let cell = if columns.len() <= 64 { 2 } else { 1 };
+    let label = rows.iter().max().map_or(1, |cpu| cpu.to_string().len());
+    let digits = columns.iter().max().map_or(1, |cpu| cpu.to_string().len());
+    let mut out = String::new();
+
+    for digit in 0..digits {
+        write!(out, "{:label$} ", "").unwrap();
+        for (i, cpu) in columns.iter().enumerate() {
+            if lines && column_breaks[i] {
+                out.push(' ');
+            }
+            let id = format!("{cpu:>digits$}");
+            write!(out, "{:>cell$}", &id[digit..digit + 1]).unwrap();
+        }
+        out.push('\n');
+    }
+
+    for (r, &main_core) in rows.iter().enumerate() {
+        if lines && row_breaks[r] {
+            write!(out, "{:label$} ", "").unwrap();
+            for &column_break in &column_breaks {
+                if column_break {
+                    out.push('┼');
+                }
+                out.push_str(&"─".repeat(cell));
+            }
+            out.push('\n');
+        }
+        write!(out, "{main_core:>label$} ").unwrap();
+        for (i, &worker_core) in columns.iter().enumerate() {
+            if lines && column_breaks[i] {
+                out.push('│');
+            }
+            match value(main_core, worker_core) {
+                Some(ns) => {
+                    write!(out, "{}{:cell$}{RESET}", colour.background(scale(ns)), "").unwrap()
+                }
+                None => write!(out, "{:>cell$}", "·").unwrap(),
+            }
+        }
+        out.push('\n');
+    }
+
+    if !values.is_empty() {
+        write!(out, "\n{min:.1} ns ").unwrap();
+        for step in 0..LEGEND_STEPS {
+            let t = step as f64 / (LEGEND_STEPS - 1) as f64;
+            write!(out, "{}  {RESET}", colour.background(t)).unwrap();
+        }
+        writeln!(out, " {max:.1} ns per op, rows main, columns worker").unwrap();
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::tests::topology;
+
+    #[test]
+    fn order_groups_cpus_by_cluster() {
+        let topology = topology();
+        assert_eq!(order(&topology, &[5, 0, 7, 2, 1, 4]), [0, 1, 2, 4, 5, 7]);
+        //> cpus the topology doesn't know have no cluster and sort first
+        assert_eq!(order(&topology, &[3, 9, 0]), [9, 0, 3]);
+    }
+
+    #[test]
+    fn boundaries_mark_where_a_cluster_starts() {
+        let topology = topology();
+        assert_eq!(
+            boundaries(&topology, &[0, 1, 2, 4, 5]),
+            [false, false, true, true, false]
+        );
+        assert_eq!(boundaries(&topology, &[6]), [false]);
+        assert!(boundaries(&topology, &[]).is_empty());
+    }
+}
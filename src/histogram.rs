@@ -0,0 +1,213 @@
+//> log-linear histogram in the spirit of hdr histogram: every power of two
+//> is split into SUB_BUCKETS linear buckets, so the relative error of any
+//> reported value stays below 1 / SUB_BUCKETS (~3%)
+const SUB_BITS: u32 = 5;
+const SUB_BUCKETS: usize = 1 << SUB_BITS;
+const BUCKETS: usize = (64 - SUB_BITS as usize + 1) * SUB_BUCKETS;
+
+#[derive(Clone)]
+pub struct Histogram {
+    //> empty once frozen
+    counts: Vec<u64>,
+    //> the PERCENTILES, set by `freeze`
+    frozen: Option<[u64; PERCENTILES.len()]>,
+    count: u64,
+    sum: u128,
+    //> for the variance, f64 since squared cycle counts overflow quickly
+    sum_sq: f64,
+    min: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Histogram {
+    pub fn new() -> Self {
+        Self {
+            counts: vec![0; BUCKETS],
+            frozen: None,
+            count: 0,
+            sum: 0,
+            sum_sq: 0.0,
+            min: u64::MAX,
+            max: 0,
+        }
+    }
+
+    #[inline(always)]
+    fn index(value: u64) -> usize {
+        if value < SUB_BUCKETS as u64 {
+            return value as usize;
+        }
+        let shift = 63 - value.leading_zeros() - SUB_BITS;
+        let mantissa = (value >> shift) as usize;
+        (shift as usize + 1) * SUB_BUCKETS + (mantissa - SUB_BUCKETS)
+    }
+
+    //> largest value that maps into bucket `index`
+    fn upper_bound(index: usize) -> u64 {
+        if index < SUB_BUCKETS {
+            return index as u64;
+        }
+        let shift = index / SUB_BUCKETS - 1;
+        let mantissa = (SUB_BUCKETS + index % SUB_BUCKETS) as u64;
+        ((mantissa + 1) << shift).wrapping_sub(1)
+    }
+
+    #[inline(always)]
+    pub fn record(&mut self, value: u64) {
+        self.counts[Self::index(value)] += 1;
+        self.count += 1;
+        self.sum += value as u128;
+        self.sum_sq += (value as f64) * (value as f64);
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    //> keep the moments, min, max and PERCENTILES and drop the 15 KB of
+    //> buckets, for results that are held on to, e.g. every pair of a matrix.
+    //> Other quantiles are None afterwards and it must not record or merge
+    pub fn freeze(&mut self) {
+        if self.frozen.is_none() {
+            let percentiles = PERCENTILES.map(|(_, q)| self.value_at_quantile(q).unwrap_or(0));
+            self.frozen = Some(percentiles);
+            self.counts = Vec::new();
+        }
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        debug_assert!(self.frozen.is_none() && other.frozen.is_none());
+        for (a, b) in self.counts.iter_mut().zip(&other.counts) {
+            *a += b;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        self.sum_sq += other.sum_sq;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn min(&self) -> Option<u64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    pub fn max(&self) -> Option<u64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum as f64 / self.count as f64)
+    }
+
+    //> sample variance of the exact recorded values
+    pub fn variance(&self) -> Option<f64> {
+        if self.count < 2 {
+            return None;
+        }
+        let n = self.count as f64;
+        let mean = self.sum as f64 / n;
+        Some(((self.sum_sq - n * mean * mean) / (n - 1.0)).max(0.0))
+    }
+
+    pub fn stddev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+
+    //> value at quantile `q` in [0, 1], reported as the bucket's upper bound
+    pub fn value_at_quantile(&self, q: f64) -> Option<u64> {
+        if self.count == 0 {
+            return None;
+        }
+        if let Some(percentiles) = self.frozen {
+            let i = PERCENTILES.iter().position(|&(_, p)| p == q)?;
+            return Some(percentiles[i]);
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, &n) in self.counts.iter().enumerate() {
+            seen += n;
+            if seen >= rank {
+                return Some(Self::upper_bound(index).clamp(self.min, self.max));
+            }
+        }
+        Some(self.max)
+    }
+}
+
+pub const PERCENTILES: [(&str, f64); 5] = [
+    ("p50", 0.50),
+    ("p90", 0.90),
+    ("p99", 0.99),
+    ("p99.9", 0.999),
+    ("p99.99", 0.9999),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_values_get_a_bucket_each() {
+        for value in 0..SUB_BUCKETS as u64 * 2 {
+            assert_eq!(Histogram::index(value), value as usize);
+            assert_eq!(Histogram::upper_bound(value as usize), value);
+        }
+    }
+
+    #[test]
+    fn buckets_widen_past_64() {
+        assert_eq!(Histogram::index(64), 64);
+        assert_eq!(Histogram::index(65), 64);
+        assert_eq!(Histogram::upper_bound(64), 65);
+        assert_eq!(Histogram::index(66), 65);
+        assert_eq!(Histogram::index(127), 95);
+        assert_eq!(Histogram::index(128), 96);
+    }
+
+    #[test]
+    fn upper_bound_is_the_last_value_of_its_bucket() {
+        for index in 0..BUCKETS - 1 {
+            let bound = Histogram::upper_bound(index);
+            assert_eq!(Histogram::index(bound), index, "bucket {index}");
+            assert_eq!(Histogram::index(bound + 1), index + 1, "bucket {index}");
+        }
+    }
+
+    #[test]
+    fn frozen_keeps_moments_and_percentiles() {
+        let mut h = Histogram::new();
+        for value in 1..=10_000 {
+            h.record(value);
+        }
+        let before = h.clone();
+        h.freeze();
+        assert!(h.counts.is_empty());
+        for (_, q) in PERCENTILES {
+            assert_eq!(h.value_at_quantile(q), before.value_at_quantile(q));
+        }
+        assert_eq!(h.value_at_quantile(0.25), None);
+        assert_eq!(
+            (h.count(), h.min(), h.max()),
+            (10_000, Some(1), Some(10_000))
+        );
+        assert_eq!(h.mean(), before.mean());
+        assert_eq!(h.variance(), before.variance());
+    }
+
+    #[test]
+    fn u64_max_lands_in_the_last_bucket() {
+        assert_eq!(Histogram::index(u64::MAX), BUCKETS - 1);
+        assert_eq!(Histogram::upper_bound(BUCKETS - 1), u64::MAX);
+        let mut h = Histogram::new();
+        h.record(u64::MAX);
+        assert_eq!(h.count(), 1);
+    }
+}
@@ -0,0 +1,59 @@
+use std::{ffi::CStr, fs};
+
+pub struct Host {
+    pub hostname: String,
+    pub kernel: String,
+    pub arch: String,
+    pub cpu_model: Option<String>,
+    //> None when the kernel does not expose smt control
+    pub smt_active: Option<bool>,
+}
+
+fn field(bytes: &[libc::c_char]) -> String {
+    unsafe { CStr::from_ptr(bytes.as_ptr()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+impl Host {
+    pub fn discover() -> Self {
+        let mut uts: libc::utsname = unsafe { std::mem::zeroed() };
+        let uname_ok = unsafe { libc::uname(&mut uts) } == 0;
+        let (hostname, kernel, arch) = if uname_ok {
+            (
+                field(&uts.nodename),
+                format!(
+                    "{} {} {}",
+                    field(&uts.sysname),
+                    field(&uts.release),
+                    field(&uts.version)
+                ),
+                field(&uts.machine),
+            )
+        } else {
+            Default::default()
+        };
+
+        let cpu_model = fs::read_to_string("/proc/cpuinfo")
+            .ok()
+            .and_then(|cpuinfo| {
+                cpuinfo
+                    .lines()
+                    .find(|line| line.starts_with("model name"))
+                    .and_then(|line| line.split_once(':'))
+                    .map(|(_, model)| model.trim().to_string())
+            });
+
+        let smt_active = fs::read_to_string("/sys/devices/system/cpu/smt/active")
+            .ok()
+            .map(|active| active.trim() == "1");
+
+        Self {
+            hostname,
+            kernel,
+            arch,
+            cpu_model,
+            smt_active,
+        }
+    }
+}
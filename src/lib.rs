@@ -0,0 +1,45 @@
+//! core-to-core latency probes: two threads pinned to a pair of cores bounce
+//! a pair of counters between each other and time the round trips.
+//!
+//! ```no_run
+//! use coreping::{measure, PingPongConfig};
+//! use std::time::Duration;
+//!
+//! let config = PingPongConfig::builder(0, 1)
+//!     .iterations(1_000_000)
+//!     .timeout(Duration::from_secs(5))
+//!     .build()?;
+//! let m = measure(&config)?;
+//! println!("{:?} ns per op", m.ns_per_op());
+//! # Ok::<(), coreping::Error>(())
+//! ```
+
+mod affinity;
+pub mod clock;
+pub mod compare;
+pub mod cpulist;
+mod cpumask;
+mod error;
+pub mod heatmap;
+pub mod histogram;
+pub mod host;
+mod json;
+pub mod lines;
+pub mod matrix;
+mod pingpong;
+pub mod protocol;
+pub mod report;
+pub mod signal;
+pub mod stats;
+pub mod svg;
+pub mod topology;
+pub mod usable;
+mod watchdog;
+
+pub use error::Error;
+pub use pingpong::{
+    measure, measure_runs, Adaptive, AdaptiveResult, Directional, Measurement, OneWay,
+    PingPongConfig, PingPongConfigBuilder, StopMode, Warmup, WarmupResult, WorkerStats,
+    DEFAULT_BATCH, DEFAULT_ITERATIONS, DEFAULT_STALL, DEFAULT_WARMUP,
+};
+pub use watchdog::StopReason;
@@ -0,0 +1,308 @@
+use crate::cpumask::CpuMask;
+use std::{
+    fmt, io, ptr,
+    sync::atomic::{AtomicBool, AtomicU64, AtomicU8},
+};
+
+const PAGE: usize = 4096;
+//> far past any prefetcher or page effect, and bounds the mapping
+pub const MAX_STRIDE: usize = 4 << 20;
+//> mbind flags, fail unless every page ends up on the node, moving any that are not
+const MPOL_MF_STRICT: libc::c_uint = 1 << 0;
+const MPOL_MF_MOVE: libc::c_uint = 1 << 1;
+//> get_mempolicy flags, return the node backing the page at the address
+const MPOL_F_NODE: libc::c_ulong = 1 << 0;
+const MPOL_F_ADDR: libc::c_ulong = 1 << 1;
+
+//> where s1 and s2 live relative to each other
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Placement {
+    //> both flags in one 64 byte line
+    SameLine,
+    //> each flag at the start of its own `line` byte aligned line
+    Separate { line: usize },
+    //> s2 placed `bytes` after s1, both line aligned when `bytes` allows
+    Stride { bytes: usize },
+}
+
+impl Placement {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let placement = match s.split_once(':') {
+            None if s == "same-line" => Placement::SameLine,
+            None if s == "separate" => Placement::Separate { line: 64 },
+            Some(("separate", line)) => Placement::Separate {
+                line: line
+                    .parse()
+                    .map_err(|_| format!("invalid line size {line:?}"))?,
+            },
+            Some(("stride", bytes)) => Placement::Stride {
+                bytes: bytes
+                    .parse()
+                    .map_err(|_| format!("invalid stride {bytes:?}"))?,
+            },
+            _ => {
+                return Err(format!(
+                "unknown placement {s:?}, expected same-line, separate[:64|128] or stride:<bytes>"
+            ))
+            }
+        };
+        match placement {
+            Placement::Separate { line } if line != 64 && line != 128 => {
+                Err(format!("line size must be 64 or 128, got {line}"))
+            }
+            Placement::Stride { bytes } if bytes < 8 || bytes % 8 != 0 => Err(format!(
+                "stride must be a non-zero multiple of 8, got {bytes}"
+            )),
+            Placement::Stride { bytes } if bytes > MAX_STRIDE => Err(format!(
+                "stride must be at most {MAX_STRIDE} bytes, got {bytes}"
+            )),
+            placement => Ok(placement),
+        }
+    }
+
+    //> the spelling accepted by `parse`
+    pub fn spec(self) -> String {
+        match self {
+            Placement::SameLine => "same-line".into(),
+            Placement::Separate { line } => format!("separate:{line}"),
+            Placement::Stride { bytes } => format!("stride:{bytes}"),
+        }
+    }
+
+    //> byte offsets of the timestamps carried along with s1 and s2 in
+    //> directional mode: the slot after each flag, skipping the other flag
+    //> when the two are adjacent
+    pub fn stamp_offsets(self) -> (usize, usize) {
+        match self.offset() {
+            8 => (16, 24),
+            16 => (8, 24),
+            s2 => (8, s2 + 8),
+        }
+    }
+
+    //> byte offset of s2 from s1
+    pub fn offset(self) -> usize {
+        match self {
+            Placement::SameLine => 8,
+            Placement::Separate { line } => line,
+            Placement::Stride { bytes } => bytes,
+        }
+    }
+}
+
+impl fmt::Display for Placement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Placement::SameLine => write!(f, "same line"),
+            Placement::Separate { line } => write!(f, "separate {line} byte lines"),
+            Placement::Stride { bytes } => write!(f, "stride {bytes} bytes"),
+        }
+    }
+}
+
+//> page aligned mapping holding the two ping-pong flags and, on a line
+//> of its own, the stop flag both threads poll plus the reason it was raised.
+//> A mapping of its own rather than the heap, so a memory policy set on it
+//> never leaks into unrelated allocations
+pub struct Lines {
+    base: *mut u8,
+    size: usize,
+    s2_offset: usize,
+    stamp_offsets: (usize, usize),
+    stop_offset: usize,
+}
+
+//> the mapping is only ever accessed through the atomics
+unsafe impl Send for Lines {}
+unsafe impl Sync for Lines {}
+
+impl Lines {
+    pub fn new(placement: Placement) -> io::Result<Self> {
+        let s2_offset = placement.offset();
+        //> 128 bytes clear of s2 so the adjacent-line prefetcher leaves it alone
+        //> the fields are public, so a placement that skipped `parse` may still
+        //> be arbitrarily far out
+        let layout = s2_offset
+            .checked_add(128)
+            .and_then(|end| end.checked_next_multiple_of(128))
+            .and_then(|stop| Some((stop, stop.checked_add(128)?.checked_next_multiple_of(PAGE)?)));
+        let Some((stop_offset, size)) = layout else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("s2 offset of {s2_offset} bytes does not fit a mapping"),
+            ));
+        };
+        //> anonymous pages read as zero, a valid pair of AtomicU64(0)
+        let base = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            base: base.cast(),
+            size,
+            s2_offset,
+            stamp_offsets: placement.stamp_offsets(),
+            stop_offset,
+        })
+    }
+
+    #[inline(always)]
+    pub fn s1(&self) -> &AtomicU64 {
+        unsafe { &*(self.base as *const AtomicU64) }
+    }
+
+    #[inline(always)]
+    pub fn s2(&self) -> &AtomicU64 {
+        unsafe { &*(self.base.add(self.s2_offset) as *const AtomicU64) }
+    }
+
+    //> main's send time, written before each s1 publish in directional mode
+    #[inline(always)]
+    pub fn s1_stamp(&self) -> &AtomicU64 {
+        unsafe { &*(self.base.add(self.stamp_offsets.0) as *const AtomicU64) }
+    }
+
+    //> the worker's send time, written right before each s2 publish
+    #[inline(always)]
+    pub fn s2_stamp(&self) -> &AtomicU64 {
+        unsafe { &*(self.base.add(self.stamp_offsets.1) as *const AtomicU64) }
+    }
+
+    #[inline(always)]
+    pub fn stop(&self) -> &AtomicBool {
+        unsafe { &*(self.base.add(self.stop_offset) as *const AtomicBool) }
+    }
+
+    pub fn stop_reason(&self) -> &AtomicU8 {
+        unsafe { &*(self.base.add(self.stop_offset + 8) as *const AtomicU8) }
+    }
+}
+
+impl Lines {
+    //> home the pages on numa `node` with mbind and fault them in there, so
+    //> every coherence transaction goes through that node's directory
+    pub fn bind(&self, node: usize) -> io::Result<()> {
+        //> a nodemask has the same unsigned long bitmap layout as a cpu mask
+        let nodes = CpuMask::single(node);
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_mbind,
+                self.base,
+                self.size,
+                libc::MPOL_BIND,
+                nodes.as_ptr(),
+                //> the kernel reads maxnode - 1 bits
+                nodes.size_bytes() * 8 + 1,
+                MPOL_MF_STRICT | MPOL_MF_MOVE,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        //> write faults so each page is allocated under the policy, a read
+        //> fault would map the shared zero page
+        for offset in (0..self.size).step_by(PAGE) {
+            unsafe { ptr::write_volatile(self.base.add(offset), 0) }
+        }
+        Ok(())
+    }
+
+    //> numa node of the page holding the flags, as the kernel reports it
+    pub fn node(&self) -> io::Result<usize> {
+        let mut node: libc::c_int = -1;
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_get_mempolicy,
+                &mut node,
+                ptr::null_mut::<libc::c_ulong>(),
+                0 as libc::c_ulong,
+                self.base,
+                MPOL_F_NODE | MPOL_F_ADDR,
+            )
+        };
+        if ret != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(node as usize)
+    }
+}
+
+impl Drop for Lines {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.base.cast(), self.size) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_placement() {
+        assert_eq!(Placement::parse("same-line"), Ok(Placement::SameLine));
+        assert_eq!(
+            Placement::parse("separate"),
+            Ok(Placement::Separate { line: 64 })
+        );
+        assert_eq!(
+            Placement::parse("separate:128"),
+            Ok(Placement::Separate { line: 128 })
+        );
+        assert_eq!(
+            Placement::parse("stride:4096"),
+            Ok(Placement::Stride { bytes: 4096 })
+        );
+        for placement in [
+            Placement::SameLine,
+            Placement::Separate { line: 128 },
+            Placement::Stride { bytes: 24 },
+        ] {
+            assert_eq!(Placement::parse(&placement.spec()), Ok(placement));
+        }
+    }
+
+    #[test]
+    fn rejects_bad_placements() {
+        for spec in [
+            "",
+            "same",
+            "separate:32",
+            "separate:x",
+            "stride:0",
+            "stride:12",
+            "stride:-8",
+            "stride:18446744073709551552",
+        ] {
+            assert!(Placement::parse(spec).is_err(), "{spec:?}");
+        }
+        assert!(Placement::parse(&format!("stride:{MAX_STRIDE}")).is_ok());
+        assert!(Placement::parse(&format!("stride:{}", MAX_STRIDE + 8)).is_err());
+    }
+
+    #[test]
+    fn stamps_never_overlap_the_flags() {
+        assert_eq!(Placement::SameLine.stamp_offsets(), (16, 24));
+        assert_eq!(Placement::Stride { bytes: 16 }.stamp_offsets(), (8, 24));
+        assert_eq!(Placement::Separate { line: 64 }.stamp_offsets(), (8, 72));
+    }
+
+    #[test]
+    fn lines_fit_their_mapping() {
+        let lines = Lines::new(Placement::Stride { bytes: 4096 }).unwrap();
+        assert!(lines.stop_offset + 8 < lines.size);
+        assert_eq!(lines.size % PAGE, 0);
+        assert!(Lines::new(Placement::Stride {
+            bytes: usize::MAX - 63
+        })
+        .is_err());
+    }
+}
@@ -1,146 +1,512 @@
-use libc::{
-    cpu_set_t, getpid, pthread_setaffinity_np, pthread_t, sched_setaffinity, CPU_SET, CPU_ZERO,
+mod cli;
+
+use coreping::{
+    clock::Clock,
+    compare,
+    heatmap::{self, Colour},
+    host::Host,
+    lines::Placement,
+    matrix, measure, measure_runs,
+    protocol::{Protocol, Write},
+    report::{self, Format},
+    svg,
+    topology::{self, Topology},
+    usable::UsableCpus,
+    Adaptive, Error, Measurement, PingPongConfig, StopMode, StopReason, Warmup, DEFAULT_BATCH,
+    DEFAULT_ITERATIONS, DEFAULT_STALL, DEFAULT_WARMUP,
 };
-use std::os::unix::thread::JoinHandleExt;
 use std::{
-    env, process,
-    sync::atomic::{AtomicU64, Ordering},
-    thread,
-    time::{Duration, Instant},
+    env, fs,
+    io::{self, IsTerminal, Write as _},
+    process,
+    str::FromStr,
+    time::Duration,
 };
 
-static ITERATIONS: u64 = 500_000_000;
-static S1: AtomicU64 = AtomicU64::new(0);
-static S2: AtomicU64 = AtomicU64::new(0);
-
-unsafe fn set_main_thread_affinity(core_id: usize) {
-    let pid = getpid();
-    let mut cpu_set: cpu_set_t = std::mem::zeroed();
-    CPU_ZERO(&mut cpu_set);
-    CPU_SET(core_id, &mut cpu_set);
-
-    let ret = sched_setaffinity(
-        pid,
-        std::mem::size_of::<cpu_set_t>(),
-        &cpu_set as *const cpu_set_t,
+fn usage(program: &str) -> ! {
+    eprintln!("usage: {program} [options] <main_cpus> <worker_cpus> <timeout_seconds>");
+    eprintln!("       {program} [options] matrix <iterations> <timeout_seconds>");
+    eprintln!("       {program} compare <baseline.csv> <current.csv> [--threshold <fraction>]");
+    eprintln!("cpus are a cpulist (0-7,16-23 or 0-31:2) or node<n>, socket<n>, l3:<id>");
+    eprintln!("options:");
+    eprintln!("  --cpus <list>           cpus of the matrix (default: all online)");
+    eprintln!("  --clock tsc|monotonic   time source (default: tsc when invariant)");
+    eprintln!("  --placement <mode>      same-line (default), separate[:64|128] or stride:<bytes>");
+    eprintln!("  --ordering <set>        relaxed, acq-rel or seqcst (default: relaxed loads, seqcst write)");
+    eprintln!("  --write <primitive>     store, fetch-add (default), swap or cas");
+    eprintln!("  --format text|json|csv  output format (default: text)");
+    eprintln!(
+        "  --warmup <spec>         none, <iterations>, <n>ms or <n>s before measuring (default: 10000)"
     );
-    if ret != 0 {
-        eprintln!("failed to set affinity of main thread to cpu {core_id}: error code {ret}");
-        process::exit(1);
-    }
+    eprintln!("  --runs <n>              repeat a single pair n times and report run statistics");
+    eprintln!("  --pause-ms <ms>         sleep between runs (default: 0)");
+    eprintln!("  --precision <fraction>  measure in batches until the 95% interval is within it");
+    eprintln!("  --batch <n>             round trips per batch with --precision (default: 10000)");
+    eprintln!(
+        "  --budget <seconds>      give up converging after this long (default: 90% of the timeout)"
+    );
+    eprintln!(
+        "  --stop <mode>           watchdog (default), poll or compare (runs both, text only)"
+    );
+    eprintln!(
+        "  --stall <seconds>       stop when the partner stops answering for this long (default: 1)"
+    );
+    eprintln!(
+        "  --directional           timestamp both legs for one-way latencies (needs the tsc)"
+    );
+    eprintln!(
+        "  --color auto|always|never  heatmap for matrix output (default: auto, on a terminal)"
+    );
+    eprintln!(
+        "  --boundaries            draw lines between sockets, dies and l3 clusters in the heatmap"
+    );
+    eprintln!("  --svg <file>            also write the matrix as an svg heatmap");
+    eprintln!(
+        "  --mem-node <n>          home the shared lines on numa node n (default: first touch)"
+    );
+    eprintln!("exit codes:");
+    eprintln!("  2 invalid argument, 3 invalid cpu, 4 cpu offline, 5 cpu not in cpuset,");
+    eprintln!("  6 permission denied, 7 other affinity error, 8 timeout, 9 worker panicked,");
+    eprintln!("  10 thread not on its pinned cpu, 11 partner stalled,");
+    eprintln!("  12 cannot write an output file, 13 compare found a regression,");
+    eprintln!("  14 cannot map the lines or place them on the numa node, 128+n signal n");
+    process::exit(2);
 }
 
-unsafe fn set_pthread_affinity(thread: pthread_t, core_id: usize) {
-    let mut cpu_set: cpu_set_t = std::mem::zeroed();
-    CPU_ZERO(&mut cpu_set);
-    CPU_SET(core_id, &mut cpu_set);
-
-    let ret = pthread_setaffinity_np(
-        thread,
-        std::mem::size_of::<cpu_set_t>(),
-        &cpu_set as *const cpu_set_t,
-    );
-    if ret != 0 {
-        eprintln!("failed to set affinity of child thread to cpu {core_id}: error code {ret}");
-        process::exit(1);
+//> partial results were printed already, the exit code tells why they are partial.
+//> A run that measured nothing, because the partner never answered or warmup
+//> used up the timeout, is a plain timeout
+fn outcome(config: &PingPongConfig, results: &[Measurement]) -> Result<(), Error> {
+    //> caught after the watchdog's last look, the results are complete but the
+    //> exit code still says the run was interrupted
+    if let Some(signal) = coreping::signal::received() {
+        return Err(Error::Interrupted { signal });
     }
+    for m in results {
+        match m.stop_reason {
+            Some(StopReason::Interrupted { signal }) => return Err(Error::Interrupted { signal }),
+            Some(StopReason::Stalled) => {
+                return Err(Error::Stalled {
+                    after: config.stall,
+                })
+            }
+            _ => {}
+        }
+    }
+    if !results.is_empty() && results.iter().all(|m| m.timed_out() && m.iterations() == 0) {
+        return Err(Error::Timeout {
+            after: config.timeout,
+        });
+    }
+    Ok(())
 }
 
-fn run_thread(timeout: Instant) {
-    let mut local_val = S2.load(Ordering::Relaxed);
-    loop {
-        //> check if the timeout is reached
-        if Instant::now().duration_since(timeout) > Duration::from_secs(0) {
-            println!("timeout reached in worker thread. exiting.");
-            break;
-        }
+//> string errors from the option parsers are argument errors
+fn arg<T>(result: Result<T, String>) -> Result<T, Error> {
+    result.map_err(Error::InvalidArgument)
+}
 
-        //> wait until s1 advances
-        while local_val == S1.load(Ordering::Relaxed) {
-            if Instant::now().duration_since(timeout) > Duration::from_secs(0) {
-                return;
-            }
-        }
+fn number<T: FromStr>(value: &str, name: &str) -> Result<T, Error> {
+    value
+        .parse()
+        .map_err(|_| Error::InvalidArgument(format!("{name} must be a number, got {value:?}")))
+}
 
-        //> increment s2 once s1 changes
-        local_val = S2.fetch_add(1, Ordering::SeqCst) + 1;
+fn run() -> Result<(), Error> {
+    let args = arg(cli::Args::parse())?;
+    let program = &args.program;
+    let pos = &args.positional;
+    if pos.len() < 3 {
+        usage(program);
     }
-}
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 4 {
-        eprintln!(
-            "usage: {} <main_core> <worker_core> <timeout_seconds>",
-            args[0]
-        );
-        process::exit(-1);
+    if pos[0] == "compare" {
+        return compare_files(&args);
     }
 
-    let main_core: usize = args[1].parse().expect("invalid main_core number");
-    let worker_core: usize = args[2].parse().expect("invalid worker_core number");
-    let timeout_secs: u64 = args[3].parse().expect("invalid timeout value");
+    let clock = match args.option("clock") {
+        Some(name) => arg(Clock::from_name(name))?,
+        None => Clock::tsc().unwrap_or_else(|e| {
+            eprintln!("{e}, falling back to clock_monotonic");
+            Clock::monotonic()
+        }),
+    };
 
-    //> calculate timeout as an instant in the future
-    let timeout = Instant::now() + Duration::from_secs(timeout_secs);
+    let placement = match args.option("placement") {
+        Some(mode) => arg(Placement::parse(mode))?,
+        None => Placement::SameLine,
+    };
 
-    unsafe {
-        set_main_thread_affinity(main_core);
+    let mut protocol = Protocol::default();
+    if let Some(primitive) = args.option("write") {
+        protocol.primitive = arg(Write::parse(primitive))?;
+    }
+    if let Some(ordering) = args.option("ordering") {
+        protocol = arg(protocol.with_ordering(ordering))?;
     }
 
-    //> spawn the worker thread and pass the timeout
-    let handle = thread::spawn(move || {
-        run_thread(timeout);
+    let format = match args.option("format") {
+        Some(format) => arg(Format::parse(format))?,
+        None => Format::Text,
+    };
+
+    //> compare measures the pair once per stop mode and reports the difference
+    let compare_stop = args.option("stop") == Some("compare");
+    let stop = match args.option("stop") {
+        Some("compare") | None => StopMode::Watchdog,
+        Some(mode) => arg(StopMode::parse(mode))?,
+    };
+
+    //> the heatmap only makes sense on a terminal, pipes get the plain table
+    let heatmap = match args.option("color") {
+        Some("auto") | None => io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none(),
+        Some("always") => true,
+        Some("never") => false,
+        Some(other) => {
+            return Err(Error::InvalidArgument(format!(
+                "unknown color mode {other:?}, expected auto, always or never"
+            )))
+        }
+    };
+
+    let warmup = match args.option("warmup") {
+        Some(warmup) => arg(Warmup::parse(warmup))?,
+        None => DEFAULT_WARMUP,
+    };
+
+    let runs: usize = match args.option("runs") {
+        Some(runs) => number(runs, "runs")?,
+        None => 1,
+    };
+    let stall = match args.option("stall") {
+        Some(secs) => Duration::try_from_secs_f64(number(secs, "stall")?)
+            .map_err(|_| Error::InvalidArgument(format!("invalid stall {secs:?}")))?,
+        None => DEFAULT_STALL,
+    };
+    let pause = Duration::from_millis(match args.option("pause-ms") {
+        Some(ms) => number(ms, "pause-ms")?,
+        None => 0,
     });
 
-    //> get the raw pthread id for affinity setting
-    let thread_id = handle.as_pthread_t();
-    unsafe {
-        set_pthread_affinity(thread_id, worker_core);
+    let topology = Topology::discover();
+    //> before anything is pinned, the affinity mask is still the inherited one
+    let usable = UsableCpus::capture();
+    let matrix_mode = pos[0] == "matrix";
+    let cpus = |spec: &str, name: &str| {
+        topology
+            .select(spec)
+            .map_err(|e| Error::InvalidArgument(format!("{name}: {e}")))
+    };
+    let (rows, columns, iterations) = if matrix_mode {
+        let cpus = match args.option("cpus") {
+            Some(spec) => cpus(spec, "cpus")?,
+            //> the whole machine minus what this process may not use
+            None => {
+                let (cpus, excluded) = usable.partition(topology::online_cpus());
+                for e in excluded {
+                    eprintln!("warning: skipping {e}");
+                }
+                cpus
+            }
+        };
+        (cpus.clone(), cpus, number(&pos[1], "iterations")?)
+    } else if args.option("cpus").is_some() {
+        return Err(Error::InvalidArgument(
+            "--cpus selects the matrix cpus, pass lists as main_core and worker_core instead"
+                .into(),
+        ));
+    } else {
+        (
+            cpus(&pos[0], "main_core")?,
+            cpus(&pos[1], "worker_core")?,
+            DEFAULT_ITERATIONS,
+        )
+    };
+    //> explicitly requested cpus must all be usable
+    for &cpu in rows.iter().chain(&columns) {
+        usable.check(cpu)?;
     }
+    //> a list on either side measures every pair of the two sets
+    let sweep = matrix_mode || rows.len() > 1 || columns.len() > 1;
 
-    let start = Instant::now();
-    let mut local_val = S1.load(Ordering::Relaxed);
-
-    //> main loop: wait for s2 to match s1, then increment s1
-    while S1.load(Ordering::Relaxed) < ITERATIONS {
-        if Instant::now() >= timeout {
-            println!("timeout reached in main thread. exiting.");
-            break;
+    if compare_stop && (sweep || format != Format::Text) {
+        return Err(Error::InvalidArgument(
+            "--stop compare only works for a single pair with text output".into(),
+        ));
+    }
+    if args.option("svg").is_some() && !sweep {
+        return Err(Error::InvalidArgument(
+            "--svg draws a matrix, it needs matrix mode or a list of cpus".into(),
+        ));
+    }
+    if runs == 0 || (runs > 1 && (sweep || compare_stop)) {
+        return Err(Error::InvalidArgument(
+            "--runs must be at least 1 and more than one run only works for a single pair".into(),
+        ));
+    }
+    let timeout = Duration::from_secs(number(&pos[2], "timeout_seconds")?);
+    let mem_node = match args.option("mem-node") {
+        Some(node) => {
+            let node = number(node, "mem-node")?;
+            if !topology.nodes().any(|n| n == node) {
+                let nodes: Vec<String> = topology.nodes().map(|n| n.to_string()).collect();
+                return Err(Error::InvalidArgument(format!(
+                    "no numa node {node}, this machine has node(s) {}",
+                    nodes.join(",")
+                )));
+            }
+            Some(node)
         }
+        None => None,
+    };
 
-        //> busy spin until s2 matches local_val
-        while S2.load(Ordering::Relaxed) != local_val {
-            if Instant::now() >= timeout {
-                println!("timeout reached during busy spin in main thread. exiting.");
-                return;
+    //> a sweep sets the cores per pair
+    let first = |cpus: &[usize]| cpus.first().copied().unwrap_or(0);
+    let mut builder = PingPongConfig::builder(first(&rows), first(&columns));
+    if let Some(precision) = args.option("precision") {
+        builder = builder.adaptive(Adaptive {
+            precision: number(precision, "precision")?,
+            batch: match args.option("batch") {
+                Some(batch) => number(batch, "batch")?,
+                None => DEFAULT_BATCH,
+            },
+            budget: match args.option("budget") {
+                Some(secs) => Some(
+                    Duration::try_from_secs_f64(number(secs, "budget")?)
+                        .map_err(|_| Error::InvalidArgument(format!("invalid budget {secs:?}")))?,
+                ),
+                None => None,
+            },
+        });
+    }
+
+    if let Some(node) = mem_node {
+        builder = builder.mem_node(node);
+    }
+    let config = builder
+        .clock(clock)
+        .iterations(iterations)
+        .timeout(timeout)
+        .placement(placement)
+        .protocol(protocol)
+        .stop(stop)
+        .warmup(warmup)
+        .stall(stall)
+        .directional(args.flag("directional"))
+        .build()?;
+
+    if sweep {
+        //> created up front so a bad path fails before hours of measuring
+        let svg_file = match args.option("svg") {
+            Some(path) => Some((
+                path,
+                fs::File::create(path).map_err(|source| Error::Output {
+                    path: path.into(),
+                    source,
+                })?,
+            )),
+            None => None,
+        };
+        let matrix = matrix::run_pairs(&config, rows, columns, |m| {
+            eprintln!(
+                "cpu {} -> cpu {}: {} ns per op ({})",
+                m.main_core,
+                m.worker_core,
+                m.ns_per_op().map_or("-".to_string(), |ns| ns.to_string()),
+                topology.describe(m.main_core, m.worker_core)
+            );
+            if m.watchdog_cpu.is_none() {
+                eprintln!(
+                    "warning: no usable cpu outside the pair, the watchdog shared cpu {}",
+                    m.main_core
+                );
+            }
+        })?;
+        match format {
+            Format::Text => {
+                if heatmap {
+                    let lines = args.flag("boundaries");
+                    print!(
+                        "{}",
+                        heatmap::render(&matrix, &topology, Colour::detect(), lines)
+                    );
+                } else {
+                    matrix.print();
+                }
+                if config.directional {
+                    println!();
+                    println!("one way ns, rows send and columns receive");
+                    matrix.print_one_way();
+                }
             }
+            Format::Json => println!(
+                "{}",
+                report::json(
+                    if matrix_mode { "matrix" } else { "pairs" },
+                    &config,
+                    &topology,
+                    &Host::discover(),
+                    &matrix.results
+                )
+            ),
+            Format::Csv => print!("{}", report::csv(&topology, &matrix.results)),
         }
+        //> after the report, a failing write never costs the results
+        if let Some((path, mut file)) = svg_file {
+            file.write_all(svg::render(&matrix, &topology).as_bytes())
+                .map_err(|source| Error::Output {
+                    path: path.into(),
+                    source,
+                })?;
+        }
+        return outcome(&config, &matrix.results);
+    }
 
-        local_val = S1.fetch_add(1, Ordering::SeqCst) + 1;
+    if compare_stop {
+        return compare_stop_modes(&config, &topology);
     }
 
-    //> compute final metrics
-    let duration = start.elapsed();
-    let nanos = duration.as_nanos();
+    if runs > 1 {
+        let results = measure_runs(&config, runs, pause, |run, m| {
+            eprintln!(
+                "run {}/{runs}: {} ns per op",
+                run + 1,
+                m.ns_per_op().map_or("-".to_string(), |ns| ns.to_string())
+            );
+        })?;
+        match format {
+            Format::Text => report::print_runs_text(&config, &topology, &results),
+            Format::Json => println!(
+                "{}",
+                report::json("runs", &config, &topology, &Host::discover(), &results)
+            ),
+            //> one row per run, the pair repeats
+            Format::Csv => print!("{}", report::csv(&topology, &results)),
+        }
+        return outcome(&config, &results);
+    }
 
-    //> how many iterations actually completed
-    let final_s1 = S1.load(Ordering::SeqCst);
-    let final_s2 = S2.load(Ordering::SeqCst);
+    let results = [measure(&config)?];
+    match format {
+        Format::Text => report::print_pair_text(&config, &topology, &results[0]),
+        Format::Json => println!(
+            "{}",
+            report::json("pair", &config, &topology, &Host::discover(), &results)
+        ),
+        Format::Csv => print!("{}", report::csv(&topology, &results)),
+    }
+    outcome(&config, &results)
+}
 
-    //> each iteration has 2 ops (increment s1 + increment s2)
-    let actual_ops = final_s1 * 2;
+//> relative slowdown of a significant pair delta that fails `compare`
+const DEFAULT_THRESHOLD: f64 = 0.05;
 
-    if actual_ops > 0 {
-        let ns_per_op = nanos / actual_ops as u128;
-        let ops_sec = (actual_ops as u128 * 1_000_000_000) / nanos;
+fn compare_files(args: &cli::Args) -> Result<(), Error> {
+    let load = |path: &str| {
+        let text = fs::read_to_string(path)
+            .map_err(|e| Error::InvalidArgument(format!("cannot read {path}: {e}")))?;
+        compare::parse_csv(&text).map_err(|e| Error::InvalidArgument(format!("{path}: {e}")))
+    };
+    let (baseline, current) = (load(&args.positional[1])?, load(&args.positional[2])?);
+    let threshold: f64 = match args.option("threshold") {
+        Some(threshold) => number(threshold, "threshold")?,
+        None => DEFAULT_THRESHOLD,
+    };
+    let comparison = compare::compare(&baseline, &current);
+    if comparison.pairs.is_empty() {
+        return Err(Error::InvalidArgument(
+            "the two results have no cpu pair in common".into(),
+        ));
+    }
 
-        println!("duration = {} ns", nanos);
-        println!("ns per op = {}", ns_per_op);
-        println!("ops/sec = {}", ops_sec);
-    } else {
-        println!("no operations completed before timeout");
+    //> levels only summarise, a regression is always a pair
+    let print = |name: &str, delta: &compare::Delta, pair: bool| {
+        let line = format!(
+            "{name:<16} {:>12.2} {:>12.2} {:>+8.1}% {:>9.1} {:>10}  {}",
+            delta.baseline_ns,
+            delta.current_ns,
+            delta.change * 100.0,
+            delta.t,
+            delta.basis.label(),
+            match (pair && delta.regressed(threshold), delta.significant) {
+                (true, _) => "regression",
+                (false, true) => "significant",
+                (false, false) => "",
+            }
+        );
+        println!("{}", line.trim_end());
+    };
+    let header = |first: &str| {
+        println!(
+            "{first:<16} {:>12} {:>12} {:>9} {:>9} {:>10}",
+            "baseline ns", "current ns", "change", "t", "test"
+        )
+    };
+    header("pair");
+    for ((src, dst), delta) in &comparison.pairs {
+        print(&format!("cpu {src} -> cpu {dst}"), delta, true);
+    }
+    println!();
+    header("level");
+    for (relation, delta) in &comparison.levels {
+        print(relation, delta, false);
+    }
+    if !comparison.unmatched.is_empty() {
+        println!();
+        println!(
+            "{} pair(s) only in one of the files were skipped",
+            comparison.unmatched.len()
+        );
+    }
+    let per_sample = comparison
+        .pairs
+        .iter()
+        .any(|(_, delta)| delta.basis == compare::Basis::RoundTrips);
+    if per_sample {
+        println!();
+        println!("per sample tests treat single round trips of one run as independent and miss");
+        println!("run to run variance, so they overstate significance. save both results with");
+        println!("--runs <n>, or concatenate the csv of several runs, to test across runs");
+    }
+
+    match comparison.regressions(threshold).count() {
+        0 => Ok(()),
+        pairs => Err(Error::Regression { pairs, threshold }),
+    }
+}
+
+//> the overhead is only printed when both runs ended normally, an interrupt
+//> or a stall skips the second run
+fn compare_stop_modes(config: &PingPongConfig, topology: &Topology) -> Result<(), Error> {
+    let mut results = Vec::new();
+    for stop in [StopMode::Poll, StopMode::Watchdog] {
+        let config = config.with_stop(stop);
+        let m = measure(&config)?;
+        report::print_pair_text(&config, topology, &m);
+        println!();
+        let stopped = matches!(
+            m.stop_reason,
+            Some(StopReason::Interrupted { .. } | StopReason::Stalled)
+        );
+        results.push(m);
+        if stopped {
+            break;
+        }
     }
+    outcome(config, &results)?;
+    if let [Some(poll), Some(watchdog)] = [0, 1].map(|i| results.get(i)?.ns_per_op_f64()) {
+        println!(
+            "clock polling overhead = {:.2} ns per op (poll {poll:.2}, watchdog {watchdog:.2})",
+            poll - watchdog
+        );
+    }
+    Ok(())
+}
 
-    println!("s1 = {}, s2 = {}", final_s1, final_s2);
+fn main() {
+    coreping::signal::install();
+    if let Err(e) = run() {
+        eprintln!("error: {e}");
+        process::exit(e.exit_code());
+    }
 }
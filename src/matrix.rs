@@ -0,0 +1,116 @@
+use crate::{measure, Error, Measurement, PingPongConfig, StopReason};
+use std::collections::HashMap;
+
+pub struct Matrix {
+    //> main cores
+    pub rows: Vec<usize>,
+    //> worker cores, the same as `rows` for a full matrix
+    pub columns: Vec<usize>,
+    //> ordered pairs in row major order, the diagonal is skipped. Their
+    //> histograms are frozen, so a large matrix does not hold every bucket
+    pub results: Vec<Measurement>,
+    //> (main core, worker core) to position in `results`, renderers look up
+    //> every cell so a scan per cell would be quadratic in the pair count
+    index: HashMap<(usize, usize), usize>,
+}
+
+//> measure every ordered pair of `cpus` with the settings of `template`
+pub fn run(
+    template: &PingPongConfig,
+    cpus: Vec<usize>,
+    on_pair: impl FnMut(&Measurement),
+) -> Result<Matrix, Error> {
+    run_pairs(template, cpus.clone(), cpus, on_pair)
+}
+
+//> measure every main core of `rows` against every worker core of `columns`,
+//> calling `on_pair` after each pair completes. An interrupt ends the sweep
+//> early, the pairs measured so far are returned
+pub fn run_pairs(
+    template: &PingPongConfig,
+    rows: Vec<usize>,
+    columns: Vec<usize>,
+    mut on_pair: impl FnMut(&Measurement),
+) -> Result<Matrix, Error> {
+    let mut results = Vec::with_capacity(rows.len() * columns.len());
+
+    for &main_core in &rows {
+        for &worker_core in &columns {
+            if main_core == worker_core {
+                continue;
+            }
+            let mut m = measure(&template.with_cores(main_core, worker_core))?;
+            on_pair(&m);
+            m.freeze();
+            let interrupted = matches!(m.stop_reason, Some(StopReason::Interrupted { .. }));
+            results.push(m);
+            if interrupted {
+                return Ok(Matrix::new(rows, columns, results));
+            }
+        }
+    }
+
+    Ok(Matrix::new(rows, columns, results))
+}
+
+impl Matrix {
+    fn new(rows: Vec<usize>, columns: Vec<usize>, results: Vec<Measurement>) -> Self {
+        let index = results
+            .iter()
+            .enumerate()
+            .map(|(i, m)| ((m.main_core, m.worker_core), i))
+            .collect();
+        Self {
+            rows,
+            columns,
+            results,
+            index,
+        }
+    }
+
+    pub fn get(&self, main_core: usize, worker_core: usize) -> Option<&Measurement> {
+        let &i = self.index.get(&(main_core, worker_core))?;
+        Some(&self.results[i])
+    }
+
+    //> one-way latency from `from` to `to`, measured with `from` as main.
+    //> None unless the sweep ran in directional mode
+    pub fn one_way_ns(&self, from: usize, to: usize) -> Option<f64> {
+        let m = self.get(from, to)?;
+        let mean = m.directional.as_ref()?.main_to_worker.legs.mean()?;
+        Some(mean / m.clock.ticks_per_ns())
+    }
+
+    fn print_table(&self, cell: impl Fn(usize, usize) -> Option<String>) {
+        print!("{:>6}", "");
+        for cpu in &self.columns {
+            print!("{cpu:>6}");
+        }
+        println!();
+
+        for &main_core in &self.rows {
+            print!("{main_core:>6}");
+            for &worker_core in &self.columns {
+                match cell(main_core, worker_core) {
+                    Some(value) => print!("{value:>6}"),
+                    None => print!("{:>6}", "-"),
+                }
+            }
+            println!();
+        }
+    }
+
+    //> asymmetric table of mean one-way ns, rows send and columns receive
+    pub fn print_one_way(&self) {
+        self.print_table(|from, to| self.one_way_ns(from, to).map(|ns| format!("{ns:.0}")));
+    }
+
+    //> ns per op table, rows are the main core and columns the worker core
+    pub fn print(&self) {
+        self.print_table(|main_core, worker_core| {
+            self.get(main_core, worker_core)
+                .and_then(|m| m.ns_per_op())
+                .map(|ns| ns.to_string())
+        });
+    }
+}
@@ -0,0 +1,949 @@
+use crate::{
+    affinity::{check_cpu, set_current_thread_affinity, verify_current_cpu},
+    clock::Clock,
+    histogram::Histogram,
+    lines::{Lines, Placement},
+    protocol::Protocol,
+    stats::{self, welch_t_histograms, Z_999},
+    usable::UsableCpus,
+    watchdog::{self, StopReason, Watchdog},
+    Error,
+};
+use std::{
+    fmt, hint,
+    sync::{
+        atomic::{fence, AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+pub const DEFAULT_ITERATIONS: u64 = 500_000_000;
+
+//> how the hot loops learn that the timeout has passed
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StopMode {
+    //> a watchdog thread raises a flag at the deadline, the spins only load it
+    Watchdog,
+    //> every spin iteration reads the clock and compares it with the deadline
+    Poll,
+}
+
+impl StopMode {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "watchdog" => Ok(StopMode::Watchdog),
+            "poll" => Ok(StopMode::Poll),
+            _ => Err(format!(
+                "unknown stop mode {s:?}, expected watchdog or poll"
+            )),
+        }
+    }
+}
+
+impl fmt::Display for StopMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            StopMode::Watchdog => "watchdog",
+            StopMode::Poll => "poll",
+        })
+    }
+}
+
+//> round trips run before the measured window and kept out of it
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Warmup {
+    None,
+    Iterations(u64),
+    Time(Duration),
+}
+
+impl Warmup {
+    //> "0" or "none", an iteration count such as "10000", or a time such as "200ms" or "2s"
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid =
+            || format!("invalid warmup {s:?}, expected none, <iterations>, <n>ms or <n>s");
+        if s == "none" || s == "0" {
+            return Ok(Warmup::None);
+        }
+        if let Some(ms) = s.strip_suffix("ms") {
+            let ms = ms.parse().map_err(|_| invalid())?;
+            return Ok(Warmup::Time(Duration::from_millis(ms)));
+        }
+        if let Some(secs) = s.strip_suffix('s') {
+            let secs = secs.parse().map_err(|_| invalid())?;
+            return Ok(Warmup::Time(Duration::from_secs(secs)));
+        }
+        s.parse().map(Warmup::Iterations).map_err(|_| invalid())
+    }
+}
+
+impl fmt::Display for Warmup {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Warmup::None => write!(f, "none"),
+            Warmup::Iterations(n) => write!(f, "{n} iterations"),
+            Warmup::Time(d) => write!(f, "{} ms", d.as_millis()),
+        }
+    }
+}
+
+pub const DEFAULT_WARMUP: Warmup = Warmup::Iterations(10_000);
+pub const DEFAULT_STALL: Duration = Duration::from_secs(1);
+
+//> keep measuring in batches until the 95% interval of the mean round trip is
+//> within `precision` of it, `budget` runs out or `iterations` is reached
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Adaptive {
+    //> relative half width of the interval, 0.01 for +-1%
+    pub precision: f64,
+    pub batch: u64,
+    //> counted from the end of warmup. None ends the loop at BUDGET_SHARE of
+    //> the timeout, early enough that the watchdog does not cut it short
+    pub budget: Option<Duration>,
+}
+
+pub const DEFAULT_BATCH: u64 = 10_000;
+//> fewer batch means make a meaningless interval
+const MIN_BATCHES: usize = 5;
+//> share of the timeout, counted from the start of the pair, the default
+//> budget may spend. The rest covers watchdog granularity and teardown
+const BUDGET_SHARE: f64 = 0.9;
+
+pub struct AdaptiveResult {
+    pub batches: usize,
+    pub converged: bool,
+    pub relative_half_width: Option<f64>,
+}
+
+//> everything needed to measure one ordered pair of cores
+#[derive(Clone, Copy)]
+pub struct PingPongConfig {
+    pub main_core: usize,
+    pub worker_core: usize,
+    pub clock: Clock,
+    pub iterations: u64,
+    pub timeout: Duration,
+    pub placement: Placement,
+    pub protocol: Protocol,
+    pub stop: StopMode,
+    pub warmup: Warmup,
+    //> None measures exactly `iterations`
+    pub adaptive: Option<Adaptive>,
+    //> give up when the counters have not moved for this long
+    pub stall: Duration,
+    //> timestamp both legs of each round trip, needs the tsc
+    pub directional: bool,
+    //> numa node the lines are homed on, None leaves it to first touch
+    pub mem_node: Option<usize>,
+}
+
+pub struct PingPongConfigBuilder {
+    config: PingPongConfig,
+    //> resolved in `build`, so an explicit clock skips the tsc calibration
+    clock: Option<Clock>,
+}
+
+impl PingPongConfig {
+    pub fn builder(main_core: usize, worker_core: usize) -> PingPongConfigBuilder {
+        PingPongConfigBuilder {
+            clock: None,
+            config: PingPongConfig {
+                main_core,
+                worker_core,
+                //> placeholder until `build` knows the clock
+                clock: Clock::monotonic(),
+                iterations: DEFAULT_ITERATIONS,
+                timeout: Duration::from_secs(10),
+                placement: Placement::SameLine,
+                protocol: Protocol::default(),
+                stop: StopMode::Watchdog,
+                warmup: DEFAULT_WARMUP,
+                adaptive: None,
+                stall: DEFAULT_STALL,
+                directional: false,
+                mem_node: None,
+            },
+        }
+    }
+
+    //> same settings for another pair of cores
+    pub fn with_cores(self, main_core: usize, worker_core: usize) -> Self {
+        Self {
+            main_core,
+            worker_core,
+            ..self
+        }
+    }
+
+    //> same settings with another stop mode
+    pub fn with_stop(self, stop: StopMode) -> Self {
+        Self { stop, ..self }
+    }
+}
+
+impl PingPongConfigBuilder {
+    pub fn clock(mut self, clock: Clock) -> Self {
+        self.clock = Some(clock);
+        self
+    }
+
+    pub fn iterations(mut self, iterations: u64) -> Self {
+        self.config.iterations = iterations;
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config.timeout = timeout;
+        self
+    }
+
+    pub fn placement(mut self, placement: Placement) -> Self {
+        self.config.placement = placement;
+        self
+    }
+
+    pub fn protocol(mut self, protocol: Protocol) -> Self {
+        self.config.protocol = protocol;
+        self
+    }
+
+    pub fn stop(mut self, stop: StopMode) -> Self {
+        self.config.stop = stop;
+        self
+    }
+
+    pub fn warmup(mut self, warmup: Warmup) -> Self {
+        self.config.warmup = warmup;
+        self
+    }
+
+    pub fn adaptive(mut self, adaptive: Adaptive) -> Self {
+        self.config.adaptive = Some(adaptive);
+        self
+    }
+
+    pub fn stall(mut self, stall: Duration) -> Self {
+        self.config.stall = stall;
+        self
+    }
+
+    pub fn directional(mut self, directional: bool) -> Self {
+        self.config.directional = directional;
+        self
+    }
+
+    pub fn mem_node(mut self, node: usize) -> Self {
+        self.config.mem_node = Some(node);
+        self
+    }
+
+    pub fn build(mut self) -> Result<PingPongConfig, Error> {
+        self.config.clock = self.clock.unwrap_or_else(Clock::best);
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
+
+impl PingPongConfig {
+    //> what `build` enforces. The fields are public, so `measure` checks again
+    //> for configs put together without the builder
+    pub fn validate(&self) -> Result<(), Error> {
+        self.clock.validate().map_err(Error::InvalidConfig)?;
+        if self.iterations == 0 {
+            return Err(Error::InvalidConfig("iterations must be at least 1".into()));
+        }
+        if self.directional && !self.clock.is_cycles() {
+            return Err(Error::InvalidConfig(
+                "directional mode compares timestamps taken on two cores and needs the invariant tsc"
+                    .into(),
+            ));
+        }
+        if let Some(adaptive) = self.adaptive {
+            if !(adaptive.precision > 0.0 && adaptive.precision < 1.0) {
+                return Err(Error::InvalidConfig(
+                    "precision must be between 0 and 1".into(),
+                ));
+            }
+            //> an empty batch would never enter the spin, nothing could stop it
+            if adaptive.batch == 0 {
+                return Err(Error::InvalidConfig("batch must be at least 1".into()));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Measurement {
+    pub main_core: usize,
+    pub worker_core: usize,
+    pub clock: Clock,
+    //> measured window only, warmup excluded
+    pub duration: Duration,
+    //> final raw counters, warmup included
+    pub s1: u64,
+    pub s2: u64,
+    //> None when every configured iteration completed
+    pub stop_reason: Option<StopReason>,
+    //> per round trip latency in clock ticks, one sample per completed iteration
+    pub round_trips: Histogram,
+    pub warmup: WarmupResult,
+    pub adaptive: Option<AdaptiveResult>,
+    //> polls of s2 per round trip on the main side, warmup included
+    pub spins: Histogram,
+    //> the worker's view of the same exchange, returned through the join
+    pub worker: WorkerStats,
+    //> one-way legs, only in directional mode
+    pub directional: Option<Directional>,
+    //> cpu the watchdog slept on, None when no usable cpu outside the pair
+    //> was left and it shared the main core
+    pub watchdog_cpu: Option<usize>,
+}
+
+//> one direction of the handoff: sender's timestamp before publishing to the
+//> receiver's right after it saw the flag move, in ticks, warmup included
+#[derive(Clone)]
+pub struct OneWay {
+    pub legs: Histogram,
+    //> legs that came out negative, the two cores' tscs disagree
+    pub negative: u64,
+}
+
+impl OneWay {
+    fn new() -> Self {
+        Self {
+            legs: Histogram::new(),
+            negative: 0,
+        }
+    }
+
+    #[inline(always)]
+    fn record(&mut self, sent: u64, received: u64) {
+        match received.checked_sub(sent) {
+            Some(leg) => self.legs.record(leg),
+            None => self.negative += 1,
+        }
+    }
+}
+
+pub struct Directional {
+    pub main_to_worker: OneWay,
+    pub worker_to_main: OneWay,
+}
+
+impl Directional {
+    //> a tsc offset between the cores shifts one leg up and the other down by
+    //> the same amount, a negative leg proves the offset is larger than it
+    pub fn synchronized(&self) -> bool {
+        self.main_to_worker.negative == 0 && self.worker_to_main.negative == 0
+    }
+
+    //> mean main to worker leg over the mean worker to main leg
+    pub fn asymmetry(&self) -> Option<f64> {
+        let forward = self.main_to_worker.legs.mean()?;
+        let backward = self.worker_to_main.legs.mean()?;
+        (backward > 0.0).then(|| forward / backward)
+    }
+}
+
+//> counted by the worker over every answer it published, warmup included
+#[derive(Clone)]
+pub struct WorkerStats {
+    pub iterations: u64,
+    //> ticks from one answer to the next: the wait for s1 plus one publish.
+    //> read after publishing, so the clock never delays an answer
+    pub waits: Histogram,
+    //> polls of s1 per wait
+    pub spins: Histogram,
+    //> main to worker legs in directional mode, moved into
+    //> `Measurement::directional` once the worker is joined
+    pub main_to_worker: Option<OneWay>,
+}
+
+impl WorkerStats {
+    fn new(directional: bool) -> Self {
+        Self {
+            iterations: 0,
+            waits: Histogram::new(),
+            spins: Histogram::new(),
+            main_to_worker: directional.then(OneWay::new),
+        }
+    }
+}
+
+pub struct WarmupResult {
+    pub duration: Duration,
+    pub round_trips: Histogram,
+}
+
+//> mean of a phase differs from the other by at least this much to matter
+const SIGNIFICANT_CHANGE: f64 = 0.05;
+
+impl Measurement {
+    //> drop the buckets of every histogram, see `Histogram::freeze`
+    pub fn freeze(&mut self) {
+        let mut histograms = vec![
+            &mut self.round_trips,
+            &mut self.warmup.round_trips,
+            &mut self.spins,
+            &mut self.worker.waits,
+            &mut self.worker.spins,
+        ];
+        if let Some(directional) = &mut self.directional {
+            histograms.push(&mut directional.main_to_worker.legs);
+            histograms.push(&mut directional.worker_to_main.legs);
+        }
+        for h in histograms {
+            h.freeze();
+        }
+    }
+
+    //> the metrics only cover the iterations completed before a stop
+    pub fn is_partial(&self) -> bool {
+        self.stop_reason.is_some()
+    }
+
+    pub fn timed_out(&self) -> bool {
+        self.stop_reason == Some(StopReason::Timeout)
+    }
+
+    pub fn iterations(&self) -> u64 {
+        self.round_trips.count()
+    }
+
+    //> each iteration has 2 ops (increment s1 + increment s2)
+    pub fn ops(&self) -> u64 {
+        self.iterations() * 2
+    }
+
+    pub fn ns_per_op(&self) -> Option<u128> {
+        match self.ops() {
+            0 => None,
+            ops => Some(self.duration.as_nanos() / ops as u128),
+        }
+    }
+
+    //> unrounded, for statistics across runs
+    pub fn ns_per_op_f64(&self) -> Option<f64> {
+        match self.ops() {
+            0 => None,
+            ops => Some(self.duration.as_nanos() as f64 / ops as f64),
+        }
+    }
+
+    pub fn warmup_ns_per_op(&self) -> Option<u128> {
+        match self.warmup.round_trips.count() * 2 {
+            0 => None,
+            ops => Some(self.warmup.duration.as_nanos() / ops as u128),
+        }
+    }
+
+    //> relative change of the mean round trip from warmup to the measured window
+    pub fn warmup_change(&self) -> Option<f64> {
+        let warm = self.warmup.round_trips.mean()?;
+        let steady = self.round_trips.mean()?;
+        Some((steady - warm) / warm)
+    }
+
+    //> steady state differs from warmup both statistically (welch, p < 0.001)
+    //> and practically (means at least 5% apart)
+    pub fn warmup_differs(&self) -> Option<bool> {
+        let t = welch_t_histograms(&self.round_trips, &self.warmup.round_trips)?;
+        let change = self.warmup_change()?;
+        Some(t.abs() > Z_999 && change.abs() >= SIGNIFICANT_CHANGE)
+    }
+
+    //> mean polls per wait of main over those of the worker. Both sides wait
+    //> for one line transfer each, so a ratio far from 1 points at one
+    //> direction being slower
+    pub fn spin_asymmetry(&self) -> Option<f64> {
+        let main = self.spins.mean()?;
+        let worker = self.worker.spins.mean()?;
+        (worker > 0.0).then(|| main / worker)
+    }
+}
+
+//> checked by both hot loops, monomorphized so neither mode pays for the other
+trait StopCheck: Copy + Send + 'static {
+    fn reached(self, lines: &Lines) -> bool;
+}
+
+#[derive(Clone, Copy)]
+struct FlagCheck;
+
+impl StopCheck for FlagCheck {
+    #[inline(always)]
+    fn reached(self, lines: &Lines) -> bool {
+        lines.stop().load(Ordering::Relaxed)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct DeadlineCheck {
+    clock: Clock,
+    deadline: u64,
+}
+
+impl StopCheck for DeadlineCheck {
+    //> the flag still aborts a worker whose pinning failed
+    #[inline(always)]
+    fn reached(self, lines: &Lines) -> bool {
+        self.clock.now() >= self.deadline || lines.stop().load(Ordering::Relaxed)
+    }
+}
+
+//> two party spin barrier, so neither side is waking from a futex when the clock starts
+#[derive(Default)]
+struct StartBarrier(AtomicUsize);
+
+impl StartBarrier {
+    //> false when the stop flag went up before the other side arrived
+    fn wait(&self, lines: &Lines) -> bool {
+        self.0.fetch_add(1, Ordering::AcqRel);
+        while self.0.load(Ordering::Acquire) < 2 {
+            if lines.stop().load(Ordering::Relaxed) {
+                return false;
+            }
+            hint::spin_loop();
+        }
+        true
+    }
+}
+
+fn pin(thread: &'static str, core_id: usize) -> Result<(), Error> {
+    set_current_thread_affinity(thread, core_id)?;
+    verify_current_cpu(thread, core_id)
+}
+
+//> the worker answers every s1 advance until main raises the stop flag.
+//> DIRECTIONAL stamps the send time next to s2 right before answering
+fn run_thread<const DIRECTIONAL: bool>(
+    lines: &Lines,
+    protocol: Protocol,
+    clock: Clock,
+    stop: impl StopCheck,
+) -> WorkerStats {
+    let (s1, s2) = (lines.s1(), lines.s2());
+    let mut stats = WorkerStats::new(DIRECTIONAL);
+    let mut local_val = protocol.load(s2);
+    let mut last = clock.now();
+    loop {
+        //> wait until s1 advances
+        let mut spins = 0;
+        while local_val == protocol.load(s1) {
+            if stop.reached(lines) {
+                return stats;
+            }
+            spins += 1;
+        }
+
+        let mut leg = (0, 0);
+        if DIRECTIONAL {
+            //> pairs with main's release fence before publishing s1
+            fence(Ordering::Acquire);
+            let received = clock.now();
+            //> main stamps its next send as soon as s2 moves, read this one first
+            leg = (lines.s1_stamp().load(Ordering::Relaxed), received);
+            //> the send time is taken right before publishing, as main does, so
+            //> the worker's own bookkeeping stays out of the worker to main leg
+            lines.s2_stamp().store(clock.now(), Ordering::Relaxed);
+            fence(Ordering::Release);
+        }
+
+        //> increment s2 once s1 changes
+        local_val = protocol.publish(s2, local_val);
+
+        if let (true, Some(one_way)) = (DIRECTIONAL, &mut stats.main_to_worker) {
+            one_way.record(leg.0, leg.1);
+        }
+
+        let now = clock.now();
+        stats.waits.record(now - last);
+        stats.spins.record(spins);
+        stats.iterations += 1;
+        last = now;
+    }
+}
+
+//> main side of the exchange, carried across the warmup and measured phases
+struct MainSide<'a, S, const DIRECTIONAL: bool> {
+    lines: &'a Lines,
+    protocol: Protocol,
+    clock: Clock,
+    stop: S,
+    local_val: u64,
+    //> when the last answer from the worker was seen
+    last: u64,
+    spins: Histogram,
+    worker_to_main: OneWay,
+}
+
+//> DIRECTIONAL stamps the send time next to s1 and reads the worker's stamp
+impl<S: StopCheck, const DIRECTIONAL: bool> MainSide<'_, S, DIRECTIONAL> {
+    //> one iteration is one round trip: publish s1, spin until s2 answers.
+    //> returns false when the stop check fired before `done`
+    fn run(&mut self, round_trips: &mut Histogram, done: impl Fn(u64, u64) -> bool) -> bool {
+        let (s1, s2) = (self.lines.s1(), self.lines.s2());
+        while !done(round_trips.count(), self.last) {
+            if DIRECTIONAL {
+                self.lines
+                    .s1_stamp()
+                    .store(self.clock.now(), Ordering::Relaxed);
+                fence(Ordering::Release);
+            }
+            self.local_val = self.protocol.publish(s1, self.local_val);
+
+            //> busy spin until s2 matches local_val
+            let mut spins = 0;
+            while self.protocol.load(s2) != self.local_val {
+                if self.stop.reached(self.lines) {
+                    return false;
+                }
+                spins += 1;
+            }
+
+            //> one timestamp per round trip, never inside the spin
+            let now = self.clock.now();
+            if DIRECTIONAL {
+                fence(Ordering::Acquire);
+                let sent = self.lines.s2_stamp().load(Ordering::Relaxed);
+                self.worker_to_main.record(sent, now);
+            }
+            round_trips.record(now - self.last);
+            self.spins.record(spins);
+            self.last = now;
+        }
+        true
+    }
+}
+
+//> measured phase in batches of `settings.batch` round trips until the tick
+//> `deadline`. The flag is false when stopped, the full batches before the
+//> stop are still summarised
+fn run_adaptive<const DIRECTIONAL: bool>(
+    main: &mut MainSide<'_, impl StopCheck, DIRECTIONAL>,
+    round_trips: &mut Histogram,
+    settings: Adaptive,
+    iterations: u64,
+    deadline: u64,
+) -> (AdaptiveResult, bool) {
+    let mut batch_means = Vec::new();
+    let mut relative_half_width = None;
+    let result = |batch_means: &Vec<f64>, converged, relative_half_width| AdaptiveResult {
+        batches: batch_means.len(),
+        converged,
+        relative_half_width,
+    };
+
+    loop {
+        let batch_start = main.last;
+        let target = (round_trips.count() + settings.batch).min(iterations);
+        let batch = target - round_trips.count();
+        if !main.run(round_trips, |count, _| count >= target) {
+            return (result(&batch_means, false, relative_half_width), false);
+        }
+        batch_means.push((main.last - batch_start) as f64 / batch as f64);
+
+        if batch_means.len() >= MIN_BATCHES {
+            relative_half_width = stats::relative_half_width(&batch_means);
+            if relative_half_width.is_some_and(|w| w <= settings.precision) {
+                return (result(&batch_means, true, relative_half_width), true);
+            }
+        }
+        if round_trips.count() >= iterations || main.last >= deadline {
+            return (result(&batch_means, false, relative_half_width), true);
+        }
+    }
+}
+
+//> run the s1/s2 ping-pong between the configured cores until `iterations` or `timeout`.
+//> the calling thread becomes the main side and stays pinned to `main_core` afterwards.
+pub fn measure(config: &PingPongConfig) -> Result<Measurement, Error> {
+    config.validate()?;
+    //> fresh lines per pair so no counters or cached state carry over
+    let lines = Arc::new(Lines::new(config.placement).map_err(|source| Error::Memory { source })?);
+    if let Some(node) = config.mem_node {
+        bind_lines(&lines, node)?;
+    }
+
+    //> the cpus the watchdog may use, captured while the affinity mask is still
+    //> the inherited one
+    let usable = UsableCpus::capture();
+    pin("main", config.main_core)?;
+    //> catch out of range ids before a worker exists
+    check_cpu(config.worker_core)?;
+
+    //> the watchdog also catches signals and stalls, in poll mode it just
+    //> leaves the deadline to the hot loops
+    let pair = [config.main_core, config.worker_core];
+    let timeout = (config.stop == StopMode::Watchdog).then_some(config.timeout);
+    let watchdog = Watchdog::spawn(Arc::clone(&lines), timeout, config.stall, pair, usable);
+    let watchdog_cpu = watchdog.cpu();
+    let result = match config.stop {
+        StopMode::Watchdog => run_pair_with(config, &lines, FlagCheck),
+        StopMode::Poll => {
+            let clock = config.clock;
+            //> calculate timeout as a tick count in the future
+            let deadline = clock.now() + clock.ticks_in(config.timeout);
+            run_pair_with(config, &lines, DeadlineCheck { clock, deadline })
+        }
+    };
+    watchdog.cancel();
+    result.map(|m| Measurement { watchdog_cpu, ..m })
+}
+
+//> home the lines on `node` and confirm the kernel put them there, mbind
+//> only fails outright on a bad node or a kernel without numa support
+fn bind_lines(lines: &Lines, node: usize) -> Result<(), Error> {
+    let fail = |reason: String| Error::MemoryNode { node, reason };
+    lines
+        .bind(node)
+        .map_err(|e| fail(format!("mbind failed: {e}")))?;
+    match lines.node() {
+        Ok(actual) if actual == node => Ok(()),
+        Ok(actual) => Err(fail(format!("the kernel placed them on node {actual}"))),
+        Err(e) => Err(fail(format!("get_mempolicy failed: {e}"))),
+    }
+}
+
+//> monomorphize the hot loops for directional mode too
+fn run_pair_with(
+    config: &PingPongConfig,
+    lines: &Arc<Lines>,
+    stop: impl StopCheck,
+) -> Result<Measurement, Error> {
+    if config.directional {
+        run_pair::<true>(config, lines, stop)
+    } else {
+        run_pair::<false>(config, lines, stop)
+    }
+}
+
+fn run_pair<const DIRECTIONAL: bool>(
+    config: &PingPongConfig,
+    lines: &Arc<Lines>,
+    stop: impl StopCheck,
+) -> Result<Measurement, Error> {
+    let PingPongConfig {
+        main_core,
+        worker_core,
+        clock,
+        iterations,
+        protocol,
+        ..
+    } = *config;
+    let (s1, s2) = (lines.s1(), lines.s2());
+    //> close to when the watchdog started counting the timeout
+    let begin = clock.now();
+
+    //> the worker pins itself before touching the lines and reports back,
+    //> so not a single iteration runs on whatever cpu it was spawned on
+    let (ready, pinned) = mpsc::channel();
+    let barrier = Arc::new(StartBarrier::default());
+    let worker_lines = Arc::clone(lines);
+    let worker_barrier = Arc::clone(&barrier);
+    let handle = thread::spawn(move || {
+        let result = pin("worker", worker_core);
+        let ok = result.is_ok();
+        let _ = ready.send(result);
+        if ok && worker_barrier.wait(&worker_lines) {
+            run_thread::<DIRECTIONAL>(&worker_lines, protocol, clock, stop)
+        } else {
+            WorkerStats::new(DIRECTIONAL)
+        }
+    });
+
+    match pinned.recv() {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => {
+            let _ = handle.join();
+            return Err(e);
+        }
+        Err(_) => {
+            let _ = handle.join();
+            return Err(Error::WorkerPanicked);
+        }
+    }
+
+    //> both threads leave the barrier pinned, the clock starts right after
+    let started = barrier.wait(lines);
+    let mut main = MainSide::<_, DIRECTIONAL> {
+        lines,
+        protocol,
+        clock,
+        stop,
+        local_val: protocol.load(s1),
+        last: clock.now(),
+        spins: Histogram::new(),
+        worker_to_main: OneWay::new(),
+    };
+
+    let warmup_start = main.last;
+    let mut warmup_trips = Histogram::new();
+    let mut completed = started
+        && match config.warmup {
+            Warmup::None => true,
+            Warmup::Iterations(n) => main.run(&mut warmup_trips, |count, _| count >= n),
+            Warmup::Time(time) => {
+                let ticks = clock.ticks_in(time);
+                main.run(&mut warmup_trips, |_, last| last - warmup_start >= ticks)
+            }
+        };
+
+    let start = main.last;
+    let mut round_trips = Histogram::new();
+    let mut adaptive = None;
+    if completed {
+        match config.adaptive {
+            None => completed = main.run(&mut round_trips, |count, _| count >= iterations),
+            Some(settings) => {
+                let deadline = match settings.budget {
+                    Some(budget) => start + clock.ticks_in(budget),
+                    None => begin + clock.ticks_in(config.timeout.mul_f64(BUDGET_SHARE)),
+                };
+                let (result, finished) =
+                    run_adaptive(&mut main, &mut round_trips, settings, iterations, deadline);
+                completed = finished;
+                adaptive = Some(result);
+            }
+        }
+    }
+    //> a poll mode deadline leaves no reason behind, everything else does
+    let stop_reason = (!completed).then(|| watchdog::reason(lines).unwrap_or(StopReason::Timeout));
+
+    //> compute final metrics over the completed round trips only
+    let duration = clock.to_duration(main.last - start);
+    let warmup = WarmupResult {
+        duration: clock.to_duration(start - warmup_start),
+        round_trips: warmup_trips,
+    };
+
+    //> the worker has answered the last s1, release it
+    lines.stop().store(true, Ordering::Relaxed);
+    let mut worker = handle.join().map_err(|_| Error::WorkerPanicked)?;
+    //> the worker carries the legs exactly in directional mode
+    let directional = worker
+        .main_to_worker
+        .take()
+        .map(|main_to_worker| Directional {
+            main_to_worker,
+            worker_to_main: main.worker_to_main,
+        });
+
+    Ok(Measurement {
+        main_core,
+        worker_core,
+        clock,
+        duration,
+        s1: s1.load(Ordering::SeqCst),
+        s2: s2.load(Ordering::SeqCst),
+        stop_reason,
+        round_trips,
+        warmup,
+        adaptive,
+        spins: main.spins,
+        worker,
+        directional,
+        //> filled in by `measure`
+        watchdog_cpu: None,
+    })
+}
+
+//> repeat `measure` in process, sleeping `pause` between runs
+pub fn measure_runs(
+    config: &PingPongConfig,
+    runs: usize,
+    pause: Duration,
+    mut on_run: impl FnMut(usize, &Measurement),
+) -> Result<Vec<Measurement>, Error> {
+    let mut results = Vec::with_capacity(runs);
+    for run in 0..runs {
+        if run > 0 && !pause.is_zero() {
+            thread::sleep(pause);
+        }
+        let m = measure(config)?;
+        on_run(run, &m);
+        let interrupted = matches!(m.stop_reason, Some(StopReason::Interrupted { .. }));
+        results.push(m);
+        if interrupted {
+            break;
+        }
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> PingPongConfig {
+        PingPongConfig::builder(0, 1)
+            .clock(Clock::monotonic())
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    fn validation_catches_configs_built_without_the_builder() {
+        let adaptive = Adaptive {
+            precision: 0.01,
+            batch: 0,
+            budget: None,
+        };
+        let config = PingPongConfig {
+            adaptive: Some(adaptive),
+            ..config()
+        };
+        assert!(config.validate().is_err());
+        assert!(measure(&config).is_err());
+        assert!(PingPongConfig {
+            iterations: 0,
+            ..config
+        }
+        .validate()
+        .is_err());
+    }
+
+    #[test]
+    fn a_hand_made_tsc_clock_is_checked() {
+        let config = |ticks_per_ns| PingPongConfig {
+            clock: Clock::Tsc { ticks_per_ns },
+            ..config()
+        };
+        assert!(config(0.0).validate().is_err());
+        assert!(config(f64::NAN).validate().is_err());
+        assert_eq!(config(2.0).validate().is_ok(), cfg!(target_arch = "x86_64"));
+    }
+
+    #[test]
+    fn directional_needs_the_tsc() {
+        let config = PingPongConfig {
+            directional: true,
+            ..config()
+        };
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn parses_warmups() {
+        assert_eq!(Warmup::parse("none"), Ok(Warmup::None));
+        assert_eq!(Warmup::parse("0"), Ok(Warmup::None));
+        assert_eq!(Warmup::parse("10000"), Ok(Warmup::Iterations(10_000)));
+        assert_eq!(
+            Warmup::parse("200ms"),
+            Ok(Warmup::Time(Duration::from_millis(200)))
+        );
+        assert_eq!(
+            Warmup::parse("2s"),
+            Ok(Warmup::Time(Duration::from_secs(2)))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_warmups() {
+        for s in ["", "ms", "s", "-1", "1.5s", "10us", "2 s", "fast"] {
+            assert!(Warmup::parse(s).is_err(), "{s:?}");
+        }
+    }
+}
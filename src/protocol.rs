@@ -0,0 +1,123 @@
+use std::{
+    fmt,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+//> how a thread publishes its next counter value
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Write {
+    Store,
+    FetchAdd,
+    Swap,
+    CompareExchange,
+}
+
+impl Write {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "store" => Ok(Write::Store),
+            "fetch-add" => Ok(Write::FetchAdd),
+            "swap" => Ok(Write::Swap),
+            "cas" => Ok(Write::CompareExchange),
+            _ => Err(format!(
+                "unknown write {s:?}, expected store, fetch-add, swap or cas"
+            )),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Write::Store => "store",
+            Write::FetchAdd => "fetch-add",
+            Write::Swap => "swap",
+            Write::CompareExchange => "cas",
+        }
+    }
+}
+
+//> orderings used for the spin loads and the publishing write
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Protocol {
+    pub load: Ordering,
+    pub write: Ordering,
+    pub primitive: Write,
+}
+
+impl Default for Protocol {
+    //> the original protocol: relaxed spin loads and a seqcst fetch_add
+    fn default() -> Self {
+        Self {
+            load: Ordering::Relaxed,
+            write: Ordering::SeqCst,
+            primitive: Write::FetchAdd,
+        }
+    }
+}
+
+fn ordering_name(ordering: Ordering) -> &'static str {
+    match ordering {
+        Ordering::Relaxed => "relaxed",
+        Ordering::Acquire => "acquire",
+        Ordering::Release => "release",
+        Ordering::AcqRel => "acqrel",
+        _ => "seqcst",
+    }
+}
+
+impl Protocol {
+    //> set both orderings from "relaxed", "acq-rel" or "seqcst"
+    pub fn with_ordering(self, s: &str) -> Result<Self, String> {
+        let (load, write) = match s {
+            "relaxed" => (Ordering::Relaxed, Ordering::Relaxed),
+            "acq-rel" => (Ordering::Acquire, Ordering::Release),
+            "seqcst" => (Ordering::SeqCst, Ordering::SeqCst),
+            _ => {
+                return Err(format!(
+                    "unknown ordering {s:?}, expected relaxed, acq-rel or seqcst"
+                ))
+            }
+        };
+        Ok(Self {
+            load,
+            write,
+            ..self
+        })
+    }
+
+    #[inline(always)]
+    pub fn load(self, flag: &AtomicU64) -> u64 {
+        flag.load(self.load)
+    }
+
+    //> advance a flag this thread owns from `current` and return the new value
+    #[inline(always)]
+    pub fn publish(self, flag: &AtomicU64, current: u64) -> u64 {
+        let next = current + 1;
+        match self.primitive {
+            Write::Store => flag.store(next, self.write),
+            Write::FetchAdd => {
+                flag.fetch_add(1, self.write);
+            }
+            Write::Swap => {
+                flag.swap(next, self.write);
+            }
+            //> the owner is the only writer so the exchange never fails
+            Write::CompareExchange => {
+                let _ = flag.compare_exchange(current, next, self.write, Ordering::Relaxed);
+            }
+        }
+        next
+    }
+}
+
+impl fmt::Display for Protocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} loads, {} {}",
+            ordering_name(self.load),
+            ordering_name(self.write),
+            self.primitive.name()
+        )
+    }
+}
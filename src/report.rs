@@ -0,0 +1,509 @@
+use crate::{
+    clock::Clock,
+    histogram::{Histogram, PERCENTILES},
+    host::Host,
+    json::{object, Json},
+    stats::{Summary, BOOTSTRAP_RESAMPLES, CONFIDENCE},
+    topology::Topology,
+    Directional, Measurement, OneWay, PingPongConfig, StopReason, Warmup,
+};
+
+//> bumped whenever a field changes meaning or disappears
+pub const SCHEMA_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+    Csv,
+}
+
+impl Format {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "csv" => Ok(Format::Csv),
+            _ => Err(format!("unknown format {s:?}, expected text, json or csv")),
+        }
+    }
+}
+
+//> csv header, new columns only ever go at the end
+pub const CSV_COLUMNS: &[&str] = &[
+    "src_cpu",
+    "dst_cpu",
+    "relation",
+    "numa_distance",
+    "iterations",
+    "ns_per_op",
+    "mean_ns",
+    "median_ns",
+    "p99_ns",
+    "stddev_ns",
+    "partial",
+    "stop_reason",
+    "warmup_differs",
+    "tsc_synchronized",
+    "one_way_src_dst_ns",
+    "one_way_dst_src_ns",
+];
+
+fn print_latency(clock: Clock, name: &str, ticks: u64) {
+    if clock.is_cycles() {
+        println!(
+            "round trip {name} = {} cycles ({:.1} ns)",
+            ticks,
+            clock.to_ns(ticks)
+        );
+    } else {
+        println!("round trip {name} = {} ns", ticks);
+    }
+}
+
+fn print_round_trips(clock: Clock, h: &Histogram) {
+    if let (Some(min), Some(max)) = (h.min(), h.max()) {
+        println!("round trips recorded = {}", h.count());
+        print_latency(clock, "min", min);
+        for (name, q) in PERCENTILES {
+            print_latency(clock, name, h.value_at_quantile(q).unwrap_or(0));
+        }
+        print_latency(clock, "max", max);
+    }
+}
+
+//> the worker's side of the exchange next to main's
+fn print_worker(clock: Clock, m: &Measurement) {
+    let worker = &m.worker;
+    println!("worker answers = {} (warmup included)", worker.iterations);
+    if let (Some(p50), Some(p99)) = (
+        worker.waits.value_at_quantile(0.5),
+        worker.waits.value_at_quantile(0.99),
+    ) {
+        println!(
+            "worker wait for s1 p50 = {:.1} ns, p99 = {:.1} ns",
+            clock.to_ns(p50),
+            clock.to_ns(p99)
+        );
+    }
+    if let (Some(main), Some(worker)) = (m.spins.mean(), worker.spins.mean()) {
+        println!(
+            "spins per wait mean = main {:.1}, worker {:.1}{}",
+            main,
+            worker,
+            m.spin_asymmetry()
+                .map_or(String::new(), |ratio| format!(", main/worker {ratio:.2}"))
+        );
+    }
+}
+
+//> both legs of the round trip, main is a and the worker b
+fn print_directional(clock: Clock, m: &Measurement) {
+    let Some(directional) = &m.directional else {
+        return;
+    };
+    let (a, b) = (m.main_core, m.worker_core);
+    for (name, one_way) in [
+        (format!("cpu {a} -> cpu {b}"), &directional.main_to_worker),
+        (format!("cpu {b} -> cpu {a}"), &directional.worker_to_main),
+    ] {
+        let legs = &one_way.legs;
+        if let (Some(p50), Some(p99), Some(mean)) = (
+            legs.value_at_quantile(0.5),
+            legs.value_at_quantile(0.99),
+            legs.mean(),
+        ) {
+            println!(
+                "one way {name} mean = {:.1} ns, p50 = {:.1} ns, p99 = {:.1} ns",
+                mean / clock.ticks_per_ns(),
+                clock.to_ns(p50),
+                clock.to_ns(p99)
+            );
+        }
+    }
+    if let Some(asymmetry) = directional.asymmetry() {
+        println!("one way asymmetry = {asymmetry:.2} (cpu {a} -> cpu {b} over cpu {b} -> cpu {a})");
+    }
+    if !directional.synchronized() {
+        println!(
+            "tsc check = failed, {} + {} legs came out negative, the tscs of the two cores are not synchronized and the legs are not trustworthy",
+            directional.main_to_worker.negative, directional.worker_to_main.negative
+        );
+    }
+}
+
+fn print_header(config: &PingPongConfig, topology: &Topology) {
+    let (a, b) = (config.main_core, config.worker_core);
+    let clock = config.clock;
+
+    println!("cpu {a} -> cpu {b}: {}", topology.describe(a, b));
+
+    match clock {
+        Clock::Tsc { ticks_per_ns } => {
+            println!("clock = tsc ({:.3} ghz)", ticks_per_ns)
+        }
+        Clock::Monotonic { .. } => println!("clock = monotonic"),
+    }
+    println!("protocol = {}", config.protocol);
+    println!("stop = {}", config.stop);
+    println!(
+        "placement = {} (s2 at s1 + {} bytes)",
+        config.placement,
+        config.placement.offset()
+    );
+    if let Some(node) = config.mem_node {
+        let from = |cpu| {
+            topology
+                .cpu(cpu)
+                .and_then(|c| c.node)
+                .and_then(|home| topology.node_distance(home, node))
+                .map_or("unknown".into(), |d| d.to_string())
+        };
+        println!(
+            "memory = numa node {node} (distance {} from cpu {a}, {} from cpu {b})",
+            from(a),
+            from(b)
+        );
+    }
+}
+
+pub fn print_pair_text(config: &PingPongConfig, topology: &Topology, m: &Measurement) {
+    print_header(config, topology);
+    match m.watchdog_cpu {
+        Some(cpu) => println!("watchdog = cpu {cpu}"),
+        None => println!(
+            "watchdog = shares cpu {}, no usable cpu outside the pair",
+            m.main_core
+        ),
+    }
+
+    let nanos = m.duration.as_nanos();
+    match m.ns_per_op() {
+        Some(ns_per_op) => {
+            let ops_sec = (m.ops() as u128 * 1_000_000_000) / nanos;
+
+            println!("duration = {} ns", nanos);
+            println!("ns per op = {}", ns_per_op);
+            println!("ops/sec = {}", ops_sec);
+        }
+        None => println!("no operations completed before timeout"),
+    }
+
+    if let Some(reason) = m.stop_reason {
+        println!(
+            "partial = {reason}, {} iterations completed",
+            m.iterations()
+        );
+    }
+
+    if let Some(adaptive) = &m.adaptive {
+        println!(
+            "adaptive = {} after {} iterations in {} batches{}",
+            if adaptive.converged {
+                "converged"
+            } else {
+                "did not converge"
+            },
+            m.iterations(),
+            adaptive.batches,
+            adaptive
+                .relative_half_width
+                .map_or(String::new(), |w| format!(", 95% ci +-{:.2}%", w * 100.0))
+        );
+    }
+
+    if let Some(warmup_ns_per_op) = m.warmup_ns_per_op() {
+        println!(
+            "warmup = {} iterations in {} ns, {} ns per op",
+            m.warmup.round_trips.count(),
+            m.warmup.duration.as_nanos(),
+            warmup_ns_per_op
+        );
+        if let (Some(change), Some(differs)) = (m.warmup_change(), m.warmup_differs()) {
+            println!(
+                "steady state vs warmup = {:+.1}% ({})",
+                change * 100.0,
+                if differs {
+                    "significant"
+                } else {
+                    "not significant"
+                }
+            );
+        }
+    }
+
+    print_round_trips(m.clock, &m.round_trips);
+    print_worker(m.clock, m);
+    print_directional(m.clock, m);
+
+    println!("s1 = {}, s2 = {}", m.s1, m.s2);
+}
+
+//> per run ns per op, their summary and the round trips of all runs merged
+pub fn print_runs_text(config: &PingPongConfig, topology: &Topology, runs: &[Measurement]) {
+    print_header(config, topology);
+
+    let mut merged = Histogram::new();
+    for (i, m) in runs.iter().enumerate() {
+        let partial = m
+            .stop_reason
+            .map_or(String::new(), |reason| format!(" (partial, {reason})"));
+        match m.ns_per_op_f64() {
+            Some(ns) => println!("run {} = {:.2} ns per op{partial}", i + 1, ns),
+            None => println!("run {} = no operations completed{partial}", i + 1),
+        }
+        merged.merge(&m.round_trips);
+    }
+
+    let values: Vec<f64> = runs.iter().filter_map(Measurement::ns_per_op_f64).collect();
+    if let Some(summary) = Summary::of(&values) {
+        println!("runs = {}", summary.count);
+        println!(
+            "ns per op mean = {:.2}, median = {:.2}, stddev = {:.2}, min = {:.2}, max = {:.2}",
+            summary.mean, summary.median, summary.stddev, summary.min, summary.max
+        );
+        println!(
+            "ns per op {:.0}% ci of the mean = [{:.2}, {:.2}] (bootstrap, {} resamples)",
+            CONFIDENCE * 100.0,
+            summary.ci.0,
+            summary.ci.1,
+            BOOTSTRAP_RESAMPLES
+        );
+    }
+
+    print_round_trips(config.clock, &merged);
+}
+
+fn host_json(host: &Host) -> Json {
+    object! {
+        "hostname" => host.hostname.as_str(),
+        "kernel" => host.kernel.as_str(),
+        "arch" => host.arch.as_str(),
+        "cpu_model" => host.cpu_model.clone(),
+        "smt_active" => host.smt_active,
+    }
+}
+
+fn config_json(config: &PingPongConfig) -> Json {
+    let protocol = config.protocol;
+    object! {
+        "iterations" => config.iterations,
+        "timeout_seconds" => config.timeout.as_secs_f64(),
+        "stall_seconds" => config.stall.as_secs_f64(),
+        "directional" => config.directional,
+        "mem_node" => config.mem_node,
+        "stop" => config.stop.to_string(),
+        "adaptive" => config.adaptive.map(|adaptive| object! {
+            "precision" => adaptive.precision,
+            "batch" => adaptive.batch,
+            "budget_seconds" => adaptive.budget.map(|budget| budget.as_secs_f64()),
+        }),
+        "warmup" => match config.warmup {
+            Warmup::None => object! { "mode" => "none" },
+            Warmup::Iterations(n) => object! { "mode" => "iterations", "iterations" => n },
+            Warmup::Time(time) => object! {
+                "mode" => "time",
+                "seconds" => time.as_secs_f64(),
+            },
+        },
+        "clock" => object! {
+            "source" => config.clock.name(),
+            "ticks_per_ns" => config.clock.ticks_per_ns(),
+        },
+        "ordering" => object! {
+            "load" => format!("{:?}", protocol.load).to_lowercase(),
+            "write" => format!("{:?}", protocol.write).to_lowercase(),
+            "primitive" => protocol.primitive.name(),
+        },
+        "placement" => object! {
+            "mode" => config.placement.spec(),
+            "s2_offset_bytes" => config.placement.offset(),
+        },
+    }
+}
+
+//> latency distribution with every value in ticks and in ns
+fn histogram_json(clock: Clock, h: &Histogram) -> Json {
+    let ns = |ticks: Option<u64>| ticks.map(|t| clock.to_ns(t));
+    let mut fields = vec![
+        ("count", Json::from(h.count())),
+        (
+            "unit",
+            Json::from(if clock.is_cycles() { "cycles" } else { "ns" }),
+        ),
+        ("min", h.min().into()),
+        ("max", h.max().into()),
+        ("mean", h.mean().into()),
+        ("min_ns", ns(h.min()).into()),
+        ("max_ns", ns(h.max()).into()),
+        ("mean_ns", h.mean().map(|t| t / clock.ticks_per_ns()).into()),
+        ("stddev", h.stddev().into()),
+        (
+            "stddev_ns",
+            h.stddev().map(|t| t / clock.ticks_per_ns()).into(),
+        ),
+    ];
+    let percentiles = PERCENTILES
+        .iter()
+        .map(|&(name, q)| {
+            let ticks = h.value_at_quantile(q);
+            object! {
+                "name" => name,
+                "quantile" => q,
+                "value" => ticks,
+                "value_ns" => ns(ticks),
+            }
+        })
+        .collect();
+    fields.push(("percentiles", Json::Array(percentiles)));
+    Json::Object(fields)
+}
+
+//> polls per wait are plain counts, not clock ticks
+fn spins_json(h: &Histogram) -> Json {
+    object! {
+        "mean" => h.mean(),
+        "p50" => h.value_at_quantile(0.5),
+        "p99" => h.value_at_quantile(0.99),
+        "max" => h.max(),
+    }
+}
+
+fn result_json(config: &PingPongConfig, topology: &Topology, m: &Measurement) -> Json {
+    let (a, b) = (m.main_core, m.worker_core);
+    let nanos = m.duration.as_nanos();
+    object! {
+        "main_core" => a,
+        "worker_core" => b,
+        "relation" => topology.relation(a, b).label(),
+        "numa_distance" => topology.numa_distance(a, b),
+        "timed_out" => m.timed_out(),
+        "partial" => m.is_partial(),
+        "stop_reason" => m.stop_reason.map(StopReason::label),
+        "watchdog_cpu" => m.watchdog_cpu,
+        "duration_ns" => nanos,
+        "iterations" => m.iterations(),
+        "ops" => m.ops(),
+        "ns_per_op" => m.ns_per_op(),
+        "ops_per_sec" => (nanos > 0).then(|| m.ops() as u128 * 1_000_000_000 / nanos),
+        "s1" => m.s1,
+        "s2" => m.s2,
+        "round_trip" => histogram_json(config.clock, &m.round_trips),
+        "adaptive" => m.adaptive.as_ref().map(|adaptive| object! {
+            "batches" => adaptive.batches,
+            "converged" => adaptive.converged,
+            "relative_half_width" => adaptive.relative_half_width,
+        }),
+        "warmup" => object! {
+            "iterations" => m.warmup.round_trips.count(),
+            "duration_ns" => m.warmup.duration.as_nanos(),
+            "ns_per_op" => m.warmup_ns_per_op(),
+            "mean_change" => m.warmup_change(),
+            "significant" => m.warmup_differs(),
+        },
+        "spins_per_wait" => spins_json(&m.spins),
+        "worker" => object! {
+            "iterations" => m.worker.iterations,
+            "wait" => histogram_json(config.clock, &m.worker.waits),
+            "spins_per_wait" => spins_json(&m.worker.spins),
+        },
+        "spin_asymmetry" => m.spin_asymmetry(),
+        "one_way" => m.directional.as_ref().map(|directional| object! {
+            "main_to_worker" => histogram_json(config.clock, &directional.main_to_worker.legs),
+            "worker_to_main" => histogram_json(config.clock, &directional.worker_to_main.legs),
+            "negative_legs" => directional.main_to_worker.negative + directional.worker_to_main.negative,
+            "tsc_synchronized" => directional.synchronized(),
+            "asymmetry" => directional.asymmetry(),
+        }),
+    }
+}
+
+//> one row per measurement, main is the source and the worker the destination.
+//> latencies are of the round trip in ns, missing values are empty fields
+pub fn csv(topology: &Topology, results: &[Measurement]) -> String {
+    fn field<T: ToString>(value: Option<T>) -> String {
+        value.map_or(String::new(), |v| v.to_string())
+    }
+
+    let mut out = CSV_COLUMNS.join(",");
+    out.push('\n');
+    for m in results {
+        let (a, b) = (m.main_core, m.worker_core);
+        let clock = m.clock;
+        let ns = |ticks: Option<u64>| ticks.map(|t| format!("{:.3}", clock.to_ns(t)));
+        let ns_f64 = |ticks: Option<f64>| ticks.map(|t| format!("{:.3}", t / clock.ticks_per_ns()));
+        let h = &m.round_trips;
+        let one_way = |one_way: Option<&OneWay>| ns_f64(one_way.and_then(|o| o.legs.mean()));
+        let row = [
+            a.to_string(),
+            b.to_string(),
+            topology.relation(a, b).label().to_string(),
+            field(topology.numa_distance(a, b)),
+            m.iterations().to_string(),
+            field(m.ns_per_op_f64().map(|ns| format!("{ns:.3}"))),
+            field(ns_f64(h.mean())),
+            field(ns(h.value_at_quantile(0.5))),
+            field(ns(h.value_at_quantile(0.99))),
+            field(ns_f64(h.stddev())),
+            m.is_partial().to_string(),
+            field(m.stop_reason.map(StopReason::label)),
+            field(m.warmup_differs()),
+            field(m.directional.as_ref().map(Directional::synchronized)),
+            field(one_way(m.directional.as_ref().map(|d| &d.main_to_worker))),
+            field(one_way(m.directional.as_ref().map(|d| &d.worker_to_main))),
+        ];
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+    out
+}
+
+fn summary_json(summary: &Summary) -> Json {
+    object! {
+        "count" => summary.count,
+        "mean" => summary.mean,
+        "median" => summary.median,
+        "stddev" => summary.stddev,
+        "min" => summary.min,
+        "max" => summary.max,
+        "ci_low" => summary.ci.0,
+        "ci_high" => summary.ci.1,
+        "confidence" => CONFIDENCE,
+        "ci_method" => "bootstrap",
+    }
+}
+
+//> full report: schema version, host, configuration and one entry per pair,
+//> or per run in "runs" mode along with the summary of their ns per op
+pub fn json(
+    mode: &str,
+    config: &PingPongConfig,
+    topology: &Topology,
+    host: &Host,
+    results: &[Measurement],
+) -> Json {
+    let runs = (mode == "runs").then(|| {
+        let values: Vec<f64> = results
+            .iter()
+            .filter_map(Measurement::ns_per_op_f64)
+            .collect();
+        Summary::of(&values).map(|summary| object! { "ns_per_op" => summary_json(&summary) })
+    });
+    object! {
+        "schema_version" => SCHEMA_VERSION,
+        "tool" => object! {
+            "name" => env!("CARGO_PKG_NAME"),
+            "version" => env!("CARGO_PKG_VERSION"),
+        },
+        "mode" => mode,
+        "host" => host_json(host),
+        "config" => config_json(config),
+        "results" => Json::Array(
+            results
+                .iter()
+                .map(|m| result_json(config, topology, m))
+                .collect(),
+        ),
+        "runs" => runs.flatten(),
+    }
+}
@@ -0,0 +1,64 @@
+use libc::{c_int, sigaction, sighandler_t, SA_RESETHAND, SIGINT, SIGTERM};
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+
+//> last termination signal received, 0 for none
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+//> live `Diversion`s, i.e. watchdogs that will pick a signal up
+static ACTIVE: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" fn on_signal(signal: c_int) {
+    //> async signal safe: atomics and raise only
+    if ACTIVE.load(Ordering::SeqCst) == 0 {
+        //> nothing would report it, so die as if never caught. The handler is
+        //> reset already and the signal blocked until we return
+        unsafe { libc::raise(signal) };
+        return;
+    }
+    RECEIVED.store(signal, Ordering::SeqCst);
+}
+
+//> while alive, SIGINT and SIGTERM become a stop request instead of killing
+//> the process
+pub struct Diversion(());
+
+impl Drop for Diversion {
+    fn drop(&mut self) {
+        ACTIVE.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+pub fn divert() -> Diversion {
+    ACTIVE.fetch_add(1, Ordering::SeqCst);
+    Diversion(())
+}
+
+//> turn SIGINT and SIGTERM into a stop request picked up by the watchdog, so
+//> the completed iterations still get reported. Outside a measurement they
+//> keep their default action. SA_RESETHAND restores the default action, a
+//> second ctrl-c kills the process as usual
+pub fn install() {
+    for signal in [SIGINT, SIGTERM] {
+        unsafe {
+            let mut action: sigaction = std::mem::zeroed();
+            action.sa_sigaction = on_signal as extern "C" fn(c_int) as sighandler_t;
+            action.sa_flags = SA_RESETHAND;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+pub fn received() -> Option<i32> {
+    match RECEIVED.load(Ordering::Relaxed) {
+        0 => None,
+        signal => Some(signal),
+    }
+}
+
+pub fn name(signal: i32) -> &'static str {
+    match signal {
+        SIGINT => "SIGINT",
+        SIGTERM => "SIGTERM",
+        _ => "signal",
+    }
+}
@@ -0,0 +1,237 @@
+use crate::histogram::Histogram;
+
+//> two sided critical value of the standard normal for p < 0.001, the t
+//> distribution is indistinguishable from it at round trip sample sizes
+pub const Z_999: f64 = 3.291;
+
+//> two sided critical values of student's t for p < 0.001 at 1..=30 degrees
+//> of freedom, what a handful of runs has to clear
+const T_999: [f64; 30] = [
+    636.62, 31.60, 12.92, 8.610, 6.869, 5.959, 5.408, 5.041, 4.781, 4.587, 4.437, 4.318, 4.221,
+    4.140, 4.073, 4.015, 3.965, 3.922, 3.883, 3.850, 3.819, 3.792, 3.768, 3.745, 3.725, 3.707,
+    3.690, 3.674, 3.659, 3.646,
+];
+
+//> critical |t| for p < 0.001 at `df` degrees of freedom. Between table
+//> entries the lower df is used, which errs on the strict side, past 1000 the
+//> normal is within 0.3%
+pub fn t_999(df: f64) -> f64 {
+    match df.floor() {
+        df if df < 1.0 => f64::INFINITY,
+        df if df <= 30.0 => T_999[df as usize - 1],
+        df if df < 40.0 => T_999[29],
+        df if df < 60.0 => 3.551,
+        df if df < 120.0 => 3.460,
+        df if df < 1000.0 => 3.373,
+        _ => Z_999,
+    }
+}
+
+//> welch-satterthwaite degrees of freedom of `welch_t`
+pub fn welch_df(var_a: f64, n_a: f64, var_b: f64, n_b: f64) -> f64 {
+    let (a, b) = (var_a / n_a, var_b / n_b);
+    let df = (a + b).powi(2) / (a * a / (n_a - 1.0) + b * b / (n_b - 1.0));
+    //> both variances zero, only the sample sizes bound it
+    if df.is_nan() {
+        n_a + n_b - 2.0
+    } else {
+        df
+    }
+}
+
+//> welch's t statistic for the difference of two means
+pub fn welch_t(mean_a: f64, var_a: f64, n_a: f64, mean_b: f64, var_b: f64, n_b: f64) -> f64 {
+    let se = (var_a / n_a + var_b / n_b).sqrt();
+    if se == 0.0 {
+        return if mean_a == mean_b { 0.0 } else { f64::INFINITY };
+    }
+    (mean_a - mean_b) / se
+}
+
+//> welch's t between the means of two histograms, None with fewer than two samples
+pub fn welch_t_histograms(a: &Histogram, b: &Histogram) -> Option<f64> {
+    Some(welch_t(
+        a.mean()?,
+        a.variance()?,
+        a.count() as f64,
+        b.mean()?,
+        b.variance()?,
+        b.count() as f64,
+    ))
+}
+
+//> splitmix64, enough randomness for resampling and reproducible between runs
+pub struct SplitMix64(u64);
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    //> uniform index below `n`, the modulo bias is negligible for sample counts
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+pub const BOOTSTRAP_RESAMPLES: usize = 10_000;
+pub const CONFIDENCE: f64 = 0.95;
+
+pub fn mean(values: &[f64]) -> f64 {
+    values.iter().sum::<f64>() / values.len() as f64
+}
+
+//> linear interpolation between closest ranks of sorted values
+pub fn quantile_sorted(sorted: &[f64], q: f64) -> f64 {
+    let pos = q.clamp(0.0, 1.0) * (sorted.len() - 1) as f64;
+    let (lo, hi) = (pos.floor() as usize, pos.ceil() as usize);
+    sorted[lo] + (sorted[hi] - sorted[lo]) * (pos - lo as f64)
+}
+
+//> percentile bootstrap confidence interval of the mean
+pub fn bootstrap_mean_ci(values: &[f64], confidence: f64, resamples: usize) -> (f64, f64) {
+    let mut rng = SplitMix64::new(0x636f_7265_7069_6e67);
+    let mut means: Vec<f64> = (0..resamples)
+        .map(|_| {
+            let sum: f64 = (0..values.len())
+                .map(|_| values[rng.below(values.len())])
+                .sum();
+            sum / values.len() as f64
+        })
+        .collect();
+    means.sort_by(f64::total_cmp);
+    let tail = (1.0 - confidence) / 2.0;
+    (
+        quantile_sorted(&means, tail),
+        quantile_sorted(&means, 1.0 - tail),
+    )
+}
+
+pub struct Summary {
+    pub count: usize,
+    pub mean: f64,
+    pub median: f64,
+    //> sample standard deviation, 0 for a single value
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+    //> bootstrap interval of the mean at `CONFIDENCE`
+    pub ci: (f64, f64),
+}
+
+impl Summary {
+    pub fn of(values: &[f64]) -> Option<Self> {
+        if values.is_empty() {
+            return None;
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let mean = mean(values);
+        let stddev = if values.len() > 1 {
+            let ss: f64 = values.iter().map(|v| (v - mean) * (v - mean)).sum();
+            (ss / (values.len() - 1) as f64).sqrt()
+        } else {
+            0.0
+        };
+        Some(Self {
+            count: values.len(),
+            mean,
+            median: quantile_sorted(&sorted, 0.5),
+            stddev,
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+            ci: bootstrap_mean_ci(values, CONFIDENCE, BOOTSTRAP_RESAMPLES),
+        })
+    }
+}
+
+//> two sided 95% critical values of student's t for 1..=30 degrees of freedom
+const T_95: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+
+pub fn t_critical_95(df: usize) -> f64 {
+    match df {
+        0 => f64::INFINITY,
+        1..=30 => T_95[df - 1],
+        //> close enough to the normal beyond 30
+        _ => 1.96,
+    }
+}
+
+//> half width of the 95% t interval of the mean, relative to the mean
+pub fn relative_half_width(values: &[f64]) -> Option<f64> {
+    if values.len() < 2 {
+        return None;
+    }
+    let mean = mean(values);
+    let ss: f64 = values.iter().map(|v| (v - mean) * (v - mean)).sum();
+    let se = (ss / (values.len() - 1) as f64).sqrt() / (values.len() as f64).sqrt();
+    Some(t_critical_95(values.len() - 1) * se / mean)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summary_of_a_sample() {
+        let s = Summary::of(&[4.0, 1.0, 10.0, 3.0, 2.0]).unwrap();
+        assert_eq!(s.count, 5);
+        assert_eq!((s.mean, s.median, s.min, s.max), (4.0, 3.0, 1.0, 10.0));
+        assert!((s.stddev - 12.5f64.sqrt()).abs() < 1e-12);
+        assert!(s.min <= s.ci.0 && s.ci.0 <= s.mean && s.mean <= s.ci.1 && s.ci.1 <= s.max);
+    }
+
+    #[test]
+    fn summary_of_one_or_no_values() {
+        assert!(Summary::of(&[]).is_none());
+        let s = Summary::of(&[7.0]).unwrap();
+        assert_eq!((s.mean, s.median, s.stddev), (7.0, 7.0, 0.0));
+        assert_eq!(s.ci, (7.0, 7.0));
+    }
+
+    #[test]
+    fn relative_half_width_of_the_mean() {
+        assert!(relative_half_width(&[]).is_none());
+        assert!(relative_half_width(&[5.0]).is_none());
+        //> standard error 1 at one degree of freedom
+        let w = relative_half_width(&[9.0, 11.0]).unwrap();
+        assert!((w - 1.2706).abs() < 1e-12);
+        assert_eq!(relative_half_width(&[3.0; 4]), Some(0.0));
+    }
+
+    #[test]
+    fn t_999_errs_on_the_strict_side() {
+        assert_eq!(t_999(0.5), f64::INFINITY);
+        assert_eq!(t_999(1.0), 636.62);
+        assert_eq!(t_999(1.9), 636.62);
+        assert_eq!(t_999(35.0), t_999(30.0));
+        assert_eq!(t_999(1e6), Z_999);
+        let mut df = 1.0;
+        while df < 2000.0 {
+            assert!(t_999(df + 0.5) <= t_999(df));
+            df += 0.5;
+        }
+    }
+
+    #[test]
+    fn welch_df_bounds() {
+        //> equal variances and sizes pool to n_a + n_b - 2
+        assert!((welch_df(4.0, 10.0, 4.0, 10.0) - 18.0).abs() < 1e-12);
+        assert_eq!(welch_df(0.0, 5.0, 0.0, 7.0), 10.0);
+        //> one noisy side dominates and brings the df down to its own
+        let df = welch_df(100.0, 5.0, 1e-9, 1000.0);
+        assert!((df - 4.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,254 @@
+use crate::{
+    heatmap::{gradient, order},
+    matrix::Matrix,
+    topology::{Cpu, Topology},
+};
+use std::fmt::Write;
+
+const CELL: usize = 14;
+//> room for the cpu ids next to the cells
+const AXIS: usize = 30;
+//> room per level of topology brackets
+const BRACKET: usize = 24;
+const TITLE: usize = 28;
+const LEGEND_WIDTH: usize = 200;
+const LEGEND_HEIGHT: usize = 12;
+const LEGEND_STEPS: usize = 50;
+
+//> name of a topology level and the id a cpu has in it
+type Level = (&'static str, fn(&Cpu) -> Option<usize>);
+
+//> levels bracketed along both axes, outermost first
+const LEVELS: [Level; 2] = [("socket", |cpu| cpu.package_id), ("l3", |cpu| cpu.l3_id)];
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn rgb(t: f64) -> String {
+    let (r, g, b) = gradient(t);
+    format!("rgb({r},{g},{b})")
+}
+
+//> consecutive runs of `ordered` sharing a value of `key`, as (first, len, value)
+fn groups(
+    topology: &Topology,
+    ordered: &[usize],
+    key: fn(&Cpu) -> Option<usize>,
+) -> Vec<(usize, usize, usize)> {
+    let mut runs: Vec<(usize, usize, usize)> = Vec::new();
+    for (i, &cpu) in ordered.iter().enumerate() {
+        let Some(value) = topology.cpu(cpu).and_then(key) else {
+            continue;
+        };
+        match runs.last_mut() {
+            Some((first, len, last)) if *last == value && *first + *len == i => *len += 1,
+            _ => runs.push((i, 1, value)),
+        }
+    }
+    runs
+}
+
+//> levels that carry information on this machine
+fn levels(topology: &Topology, cpus: &[usize]) -> Vec<Level> {
+    LEVELS
+        .into_iter()
+        .filter(|(_, key)| {
+            cpus.iter()
+                .any(|&cpu| topology.cpu(cpu).and_then(key).is_some())
+        })
+        .collect()
+}
+
+//> self contained svg of the ns per op matrix: cells ordered by topology
+//> with a tooltip each, cpu ids on both axes, socket and l3 brackets and a
+//> colour scale
+pub fn render(matrix: &Matrix, topology: &Topology) -> String {
+    let rows = order(topology, &matrix.rows);
+    let columns = order(topology, &matrix.columns);
+    let levels = levels(topology, &[rows.as_slice(), columns.as_slice()].concat());
+
+    let values: Vec<f64> = matrix
+        .results
+        .iter()
+        .filter_map(|m| m.ns_per_op_f64())
+        .collect();
+    let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+    let scale = |ns: f64| {
+        if max > min {
+            (ns - min) / (max - min)
+        } else {
+            0.0
+        }
+    };
+
+    let left = TITLE + levels.len() * BRACKET + AXIS;
+    let top = TITLE + levels.len() * BRACKET + AXIS;
+    let grid_width = columns.len() * CELL;
+    let grid_height = rows.len() * CELL;
+    let width = left + grid_width.max(LEGEND_WIDTH) + 80;
+    let height = top + grid_height + 70;
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="0 0 {width} {height}" font-family="sans-serif" font-size="10">"#
+    )
+    .unwrap();
+    writeln!(out, r#"<rect width="100%" height="100%" fill="white"/>"#).unwrap();
+
+    //> axis titles
+    writeln!(
+        out,
+        r#"<text x="{}" y="16" text-anchor="middle" font-size="12">worker cpu</text>"#,
+        left + grid_width / 2
+    )
+    .unwrap();
+    writeln!(
+        out,
+        r#"<text transform="translate(16 {}) rotate(-90)" text-anchor="middle" font-size="12">main cpu</text>"#,
+        top + grid_height / 2
+    )
+    .unwrap();
+
+    //> cpu ids
+    for (i, cpu) in columns.iter().enumerate() {
+        let x = left + i * CELL + CELL / 2;
+        writeln!(
+            out,
+            r#"<text transform="translate({x} {}) rotate(-90)" dominant-baseline="middle">{cpu}</text>"#,
+            top - 4
+        )
+        .unwrap();
+    }
+    for (i, cpu) in rows.iter().enumerate() {
+        let y = top + i * CELL + CELL / 2;
+        writeln!(
+            out,
+            r#"<text x="{}" y="{y}" text-anchor="end" dominant-baseline="middle">{cpu}</text>"#,
+            left - 4
+        )
+        .unwrap();
+    }
+
+    //> brackets, the outermost level furthest from the grid
+    for (level, (name, key)) in levels.iter().enumerate() {
+        let offset = TITLE + level * BRACKET + BRACKET / 2;
+        for (first, len, value) in groups(topology, &columns, *key) {
+            let (x1, x2) = (left + first * CELL + 1, left + (first + len) * CELL - 1);
+            let y = offset + 4;
+            writeln!(
+                out,
+                r#"<path d="M{x1} {} V{y} H{x2} V{}" fill="none" stroke="black"/><text x="{}" y="{}" text-anchor="middle">{name} {value}</text>"#,
+                y + 4,
+                y + 4,
+                (x1 + x2) / 2,
+                y - 3
+            )
+            .unwrap();
+        }
+        for (first, len, value) in groups(topology, &rows, *key) {
+            let (y1, y2) = (top + first * CELL + 1, top + (first + len) * CELL - 1);
+            let x = offset + 4;
+            writeln!(
+                out,
+                r#"<path d="M{} {y1} H{x} V{y2} H{}" fill="none" stroke="black"/><text transform="translate({} {}) rotate(-90)" text-anchor="middle">{name} {value}</text>"#,
+                x + 4,
+                x + 4,
+                x - 3,
+                (y1 + y2) / 2
+            )
+            .unwrap();
+        }
+    }
+
+    //> cells, the title element is the hover tooltip
+    for (r, &main_core) in rows.iter().enumerate() {
+        for (c, &worker_core) in columns.iter().enumerate() {
+            let Some(ns) = matrix
+                .get(main_core, worker_core)
+                .and_then(|m| m.ns_per_op_f64())
+            else {
+                continue;
+            };
+            writeln!(
+                out,
+                r#"<rect x="{}" y="{}" width="{CELL}" height="{CELL}" fill="{}"><title>cpu {main_core} -&gt; cpu {worker_core}: {ns:.1} ns per op ({})</title></rect>"#,
+                left + c * CELL,
+                top + r * CELL,
+                rgb(scale(ns)),
+                escape(&topology.describe(main_core, worker_core))
+            )
+            .unwrap();
+        }
+    }
+
+    //> colour scale
+    if !values.is_empty() {
+        let y = top + grid_height + 24;
+        let step = LEGEND_WIDTH / LEGEND_STEPS;
+        for i in 0..LEGEND_STEPS {
+            let t = i as f64 / (LEGEND_STEPS - 1) as f64;
+            writeln!(
+                out,
+                r#"<rect x="{}" y="{y}" width="{step}" height="{LEGEND_HEIGHT}" fill="{}"/>"#,
+                left + i * step,
+                rgb(t)
+            )
+            .unwrap();
+        }
+        let label_y = y + LEGEND_HEIGHT + 12;
+        writeln!(
+            out,
+            r#"<text x="{left}" y="{label_y}">{min:.1} ns</text><text x="{}" y="{label_y}" text-anchor="end">{max:.1} ns per op</text>"#,
+            left + LEGEND_WIDTH
+        )
+        .unwrap();
+    }
+
+    out.push_str("</svg>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::topology::tests::topology;
+
+    #[test]
+    fn groups_are_consecutive_runs() {
+        let topology = topology();
+        let l3 = |cpu: &Cpu| cpu.l3_id;
+        assert_eq!(
+            groups(&topology, &[0, 1, 2, 3, 4], l3),
+            [(0, 2, 0), (2, 2, 1), (4, 1, 2)]
+        );
+        assert_eq!(
+            groups(&topology, &[0, 1, 2, 3, 4], |cpu| cpu.package_id),
+            [(0, 4, 0), (4, 1, 1)]
+        );
+        //> a cpu without the level splits the run around it
+        assert_eq!(groups(&topology, &[0, 9, 1], l3), [(0, 1, 0), (2, 1, 0)]);
+    }
+
+    #[test]
+    fn levels_without_ids_are_dropped() {
+        let mut topology = topology();
+        let names = |topology: &Topology, cpus: &[usize]| {
+            levels(topology, cpus)
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(&topology, &[0, 1]), ["socket", "l3"]);
+        assert!(names(&topology, &[9]).is_empty());
+        for cpu in &mut topology.cpus {
+            cpu.l3_id = None;
+        }
+        assert_eq!(names(&topology, &[0, 1]), ["socket"]);
+    }
+}
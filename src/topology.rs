@@ -0,0 +1,342 @@
+use crate::{affinity::cpu_limit, cpulist};
+use std::{collections::HashSet, fmt, fs, path::Path};
+
+const CPU_ROOT: &str = "/sys/devices/system/cpu";
+const NODE_ROOT: &str = "/sys/devices/system/node";
+
+pub struct Cpu {
+    pub id: usize,
+    pub die_id: Option<usize>,
+    pub package_id: Option<usize>,
+    pub node: Option<usize>,
+    pub smt_siblings: Vec<usize>,
+    pub l2: Vec<usize>,
+    pub l3: Vec<usize>,
+    //> id of the l3 instance, what `l3:<id>` selects
+    pub l3_id: Option<usize>,
+}
+
+pub struct Topology {
+    pub cpus: Vec<Cpu>,
+    //> (node id, distances to every node in `nodes` order)
+    nodes: Vec<(usize, Vec<u32>)>,
+    //> cpu ids the kernel supports, what cpulists are bounded by
+    limit: usize,
+}
+
+//> closest level of the hierarchy two cpus have in common
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Relation {
+    SameCpu,
+    SmtSibling,
+    SharedL2,
+    SharedL3,
+    SameDie,
+    SameSocket,
+    CrossSocket,
+    Unknown,
+}
+
+impl Relation {
+    //> short stable name for machine readable output
+    pub fn label(self) -> &'static str {
+        match self {
+            Relation::SameCpu => "same_cpu",
+            Relation::SmtSibling => "smt",
+            Relation::SharedL2 => "l2",
+            Relation::SharedL3 => "l3",
+            Relation::SameDie => "die",
+            Relation::SameSocket => "socket",
+            Relation::CrossSocket => "cross_socket",
+            Relation::Unknown => "unknown",
+        }
+    }
+}
+
+impl fmt::Display for Relation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Relation::SameCpu => "same cpu",
+            Relation::SmtSibling => "smt sibling",
+            Relation::SharedL2 => "shared l2",
+            Relation::SharedL3 => "shared l3",
+            Relation::SameDie => "same die",
+            Relation::SameSocket => "same socket",
+            Relation::CrossSocket => "cross socket",
+            Relation::Unknown => "unknown",
+        })
+    }
+}
+
+fn read(path: impl AsRef<Path>) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+fn read_id(path: impl AsRef<Path>) -> Option<usize> {
+    read(path)?.parse().ok()
+}
+
+fn read_list(path: impl AsRef<Path>) -> Vec<usize> {
+    read(path)
+        .and_then(|list| cpulist::parse(&list))
+        .unwrap_or_default()
+}
+
+//> ids of the `prefix<N>` entries in a sysfs directory, sorted
+fn indexed_entries(dir: &str, prefix: &str) -> Vec<usize> {
+    let mut ids: Vec<usize> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|e| e.file_name().to_str()?.strip_prefix(prefix)?.parse().ok())
+        .collect();
+    ids.sort_unstable();
+    ids
+}
+
+//> sysfs directory of the unified or data cache at `level`
+fn cache_dir(cpu: usize, level: u32) -> Option<String> {
+    let cache = format!("{CPU_ROOT}/cpu{cpu}/cache");
+    indexed_entries(&cache, "index")
+        .into_iter()
+        .find_map(|index| {
+            let dir = format!("{cache}/index{index}");
+            let same_level =
+                read(format!("{dir}/level")).and_then(|l| l.parse().ok()) == Some(level);
+            let kind = read(format!("{dir}/type")).unwrap_or_default();
+            (same_level && kind != "Instruction").then_some(dir)
+        })
+}
+
+//> cpus sharing the unified or data cache at `level`
+fn shared_cache(cpu: usize, level: u32) -> Vec<usize> {
+    cache_dir(cpu, level)
+        .map(|dir| read_list(format!("{dir}/shared_cpu_list")))
+        .unwrap_or_default()
+}
+
+pub fn online_cpus() -> Vec<usize> {
+    read_list(format!("{CPU_ROOT}/online"))
+}
+
+impl Topology {
+    pub fn discover() -> Self {
+        let nodes: Vec<(usize, Vec<u32>)> = indexed_entries(NODE_ROOT, "node")
+            .into_iter()
+            .map(|node| {
+                let distances = read(format!("{NODE_ROOT}/node{node}/distance"))
+                    .unwrap_or_default()
+                    .split_whitespace()
+                    .filter_map(|d| d.parse().ok())
+                    .collect();
+                (node, distances)
+            })
+            .collect();
+        let node_cpus: Vec<(usize, Vec<usize>)> = nodes
+            .iter()
+            .map(|&(node, _)| (node, read_list(format!("{NODE_ROOT}/node{node}/cpulist"))))
+            .collect();
+
+        let cpus = online_cpus()
+            .into_iter()
+            .map(|id| {
+                let topo = format!("{CPU_ROOT}/cpu{id}/topology");
+                Cpu {
+                    id,
+                    die_id: read_id(format!("{topo}/die_id")),
+                    package_id: read_id(format!("{topo}/physical_package_id")),
+                    node: node_cpus
+                        .iter()
+                        .find(|(_, cpus)| cpus.contains(&id))
+                        .map(|&(node, _)| node),
+                    smt_siblings: read_list(format!("{topo}/thread_siblings_list")),
+                    l2: shared_cache(id, 2),
+                    l3: shared_cache(id, 3),
+                    l3_id: cache_dir(id, 3).and_then(|dir| read_id(format!("{dir}/id"))),
+                }
+            })
+            .collect();
+
+        Self {
+            cpus,
+            nodes,
+            limit: cpu_limit(),
+        }
+    }
+
+    pub fn cpu(&self, id: usize) -> Option<&Cpu> {
+        self.cpus.iter().find(|cpu| cpu.id == id)
+    }
+
+    //> cpus named by `spec`: a cpulist ("0-7,16-23", "0-31:2") mixed with the
+    //> aliases "node<N>", "socket<N>" and "l3:<id>" for the online cpus of
+    //> that numa node, package or l3 instance. Duplicates are dropped, the
+    //> first occurrence keeps its place
+    pub fn select(&self, spec: &str) -> Result<Vec<usize>, String> {
+        let mut selected = Vec::new();
+        let mut seen = HashSet::new();
+        for part in spec.trim().split(',').filter(|p| !p.is_empty()) {
+            let cpus = self.select_part(part)?;
+            if cpus.is_empty() {
+                return Err(format!("{part:?} matches no online cpu"));
+            }
+            selected.extend(cpus.into_iter().filter(|&cpu| seen.insert(cpu)));
+        }
+        if selected.is_empty() {
+            return Err(format!("empty cpu list {spec:?}"));
+        }
+        Ok(selected)
+    }
+
+    fn select_part(&self, part: &str) -> Result<Vec<usize>, String> {
+        let matching = |id: &str, key: fn(&Cpu) -> Option<usize>| {
+            let id: usize = id
+                .parse()
+                .map_err(|_| format!("invalid cpu alias {part:?}"))?;
+            Ok(self
+                .cpus
+                .iter()
+                .filter(|cpu| key(cpu) == Some(id))
+                .map(|cpu| cpu.id)
+                .collect())
+        };
+        if let Some(id) = part.strip_prefix("node") {
+            matching(id, |cpu| cpu.node)
+        } else if let Some(id) = part.strip_prefix("socket") {
+            matching(id, |cpu| cpu.package_id)
+        } else if let Some(id) = part.strip_prefix("l3:") {
+            matching(id, |cpu| cpu.l3_id)
+        } else {
+            let (lo, hi, stride) = cpulist::range(part).ok_or_else(|| {
+                format!("invalid cpu list element {part:?}, expected <n>, <a>-<b>[:<stride>], node<n>, socket<n> or l3:<id>")
+            })?;
+            //> bounded before expanding, a typo like 0-2000000 stays cheap
+            if hi >= self.limit {
+                return Err(format!(
+                    "{part:?} reaches cpu {hi}, the kernel supports cpu ids below {}",
+                    self.limit
+                ));
+            }
+            Ok((lo..=hi).step_by(stride).collect())
+        }
+    }
+
+    pub fn relation(&self, a: usize, b: usize) -> Relation {
+        if a == b {
+            return Relation::SameCpu;
+        }
+        let (Some(ca), Some(cb)) = (self.cpu(a), self.cpu(b)) else {
+            return Relation::Unknown;
+        };
+        if ca.smt_siblings.contains(&b) {
+            Relation::SmtSibling
+        } else if ca.l2.contains(&b) {
+            Relation::SharedL2
+        } else if ca.l3.contains(&b) {
+            Relation::SharedL3
+        } else if ca.package_id.is_none() || cb.package_id.is_none() {
+            Relation::Unknown
+        } else if ca.package_id != cb.package_id {
+            Relation::CrossSocket
+        } else if ca.die_id.is_some() && ca.die_id == cb.die_id {
+            Relation::SameDie
+        } else {
+            Relation::SameSocket
+        }
+    }
+
+    //> ids of the numa nodes, empty on kernels without numa support
+    pub fn nodes(&self) -> impl Iterator<Item = usize> + '_ {
+        self.nodes.iter().map(|&(node, _)| node)
+    }
+
+    //> distance between two numa nodes as reported by the slit
+    pub fn node_distance(&self, a: usize, b: usize) -> Option<u32> {
+        let (_, distances) = self.nodes.iter().find(|(node, _)| *node == a)?;
+        let position = self.nodes.iter().position(|(node, _)| *node == b)?;
+        distances.get(position).copied()
+    }
+
+    //> distance between the numa nodes of two cpus
+    pub fn numa_distance(&self, a: usize, b: usize) -> Option<u32> {
+        self.node_distance(self.cpu(a)?.node?, self.cpu(b)?.node?)
+    }
+
+    //> one line description of how two cpus relate, e.g. "shared l3, numa distance 10"
+    pub fn describe(&self, a: usize, b: usize) -> String {
+        match self.numa_distance(a, b) {
+            Some(distance) => format!("{}, numa distance {distance}", self.relation(a, b)),
+            None => self.relation(a, b).to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    //> two sockets of two l3s with two cpus each, one numa node per socket
+    pub(crate) fn topology() -> Topology {
+        let cpus = (0..8)
+            .map(|id| Cpu {
+                id,
+                die_id: Some(id / 4),
+                package_id: Some(id / 4),
+                node: Some(id / 4),
+                smt_siblings: vec![id],
+                l2: vec![id],
+                l3: vec![id & !1, id | 1],
+                l3_id: Some(id / 2),
+            })
+            .collect();
+        Topology {
+            cpus,
+            nodes: vec![(0, vec![10, 21]), (1, vec![21, 10])],
+            limit: 16,
+        }
+    }
+
+    #[test]
+    fn aliases() {
+        let t = topology();
+        assert_eq!(t.select("node1"), Ok(vec![4, 5, 6, 7]));
+        assert_eq!(t.select("socket0"), Ok(vec![0, 1, 2, 3]));
+        assert_eq!(t.select("l3:2"), Ok(vec![4, 5]));
+    }
+
+    #[test]
+    fn aliases_mixed_with_lists_keep_order_and_drop_duplicates() {
+        let t = topology();
+        assert_eq!(t.select("6,node1,0-2:2"), Ok(vec![6, 4, 5, 7, 0, 2]));
+        assert_eq!(t.select("l3:0,1,socket0"), Ok(vec![0, 1, 2, 3]));
+    }
+
+    #[test]
+    fn rejected_specs() {
+        let t = topology();
+        assert!(t.select("").is_err());
+        assert!(t.select("node2").is_err());
+        assert!(t.select("nodex").is_err());
+        assert!(t.select("l3:").is_err());
+        assert!(t.select("0-7:0").is_err());
+        assert!(t.select("3-1,node0").is_err());
+    }
+
+    #[test]
+    fn ids_past_the_kernel_limit_are_rejected_before_expanding() {
+        let t = topology();
+        assert_eq!(t.select("15"), Ok(vec![15]));
+        assert!(t.select("16").is_err());
+        assert!(t.select("0-2000000000").is_err());
+        assert!(t.select("node0,0-18446744073709551615:2").is_err());
+    }
+
+    #[test]
+    fn distances() {
+        let t = topology();
+        assert_eq!(t.numa_distance(0, 5), Some(21));
+        assert_eq!(t.numa_distance(4, 5), Some(10));
+        assert_eq!(t.node_distance(1, 0), Some(21));
+        assert_eq!(t.node_distance(0, 2), None);
+    }
+}
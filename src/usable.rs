@@ -0,0 +1,151 @@
+use crate::{
+    affinity::{check_cpu, process_affinity},
+    cpulist,
+    topology::online_cpus,
+    Error,
+};
+use std::{fs, path::PathBuf, sync::OnceLock};
+
+const CPU_ROOT: &str = "/sys/devices/system/cpu";
+//> pure cgroup v2 hosts mount it here, hybrid ones below unified/
+const CGROUP_ROOTS: [&str; 2] = ["/sys/fs/cgroup", "/sys/fs/cgroup/unified"];
+
+//> the cpus this process may pin to, captured before any thread is pinned so
+//> the affinity mask is still the one the process was started with
+pub struct UsableCpus {
+    present: Vec<usize>,
+    online: Vec<usize>,
+    //> sched_getaffinity of the process, None when the call failed
+    affinity: Option<Vec<usize>>,
+    //> cpuset.cpus.effective of our cgroup v2 and the file it came from
+    cgroup: Option<(PathBuf, Vec<usize>)>,
+}
+
+fn read_list(path: impl Into<PathBuf>) -> Option<Vec<usize>> {
+    cpulist::parse(&fs::read_to_string(path.into()).ok()?)
+}
+
+//> "0-3,8" style rendering for messages
+fn format_list(cpus: &[usize]) -> String {
+    let mut sorted = cpus.to_vec();
+    sorted.sort_unstable();
+    let mut ranges: Vec<String> = Vec::new();
+    let mut i = 0;
+    while i < sorted.len() {
+        let start = sorted[i];
+        while i + 1 < sorted.len() && sorted[i + 1] == sorted[i] + 1 {
+            i += 1;
+        }
+        ranges.push(match sorted[i] {
+            end if end == start => start.to_string(),
+            end => format!("{start}-{end}"),
+        });
+        i += 1;
+    }
+    ranges.join(",")
+}
+
+//> effective cpuset of the cgroup v2 we belong to, walking up to the first
+//> ancestor with the cpuset controller enabled
+fn cgroup_cpuset() -> Option<(PathBuf, Vec<usize>)> {
+    let membership = fs::read_to_string("/proc/self/cgroup").ok()?;
+    let path = membership
+        .lines()
+        .find_map(|line| line.strip_prefix("0::"))?
+        .trim_start_matches('/');
+    CGROUP_ROOTS.iter().find_map(|root| {
+        let mut dir = PathBuf::from(root).join(path);
+        loop {
+            let file = dir.join("cpuset.cpus.effective");
+            if let Some(cpus) = read_list(&file).filter(|cpus| !cpus.is_empty()) {
+                return Some((file, cpus));
+            }
+            if dir.as_os_str() == *root || !dir.pop() {
+                return None;
+            }
+        }
+    })
+}
+
+static CAPTURED: OnceLock<UsableCpus> = OnceLock::new();
+
+impl UsableCpus {
+    //> discovered once per process, so the first call has to come before any
+    //> thread is pinned. `measure` makes one before pinning main
+    pub fn capture() -> &'static Self {
+        CAPTURED.get_or_init(Self::discover)
+    }
+
+    pub fn discover() -> Self {
+        Self {
+            present: read_list(format!("{CPU_ROOT}/present")).unwrap_or_default(),
+            online: online_cpus(),
+            affinity: process_affinity().ok(),
+            cgroup: cgroup_cpuset(),
+        }
+    }
+
+    //> the first reason `cpu` cannot be pinned to, most fundamental first
+    pub fn check(&self, cpu: usize) -> Result<(), Error> {
+        if !self.present.is_empty() && !self.present.contains(&cpu) {
+            return Err(Error::InvalidCpu {
+                cpu,
+                reason: format!(
+                    "no such cpu on this machine, present cpus are {}",
+                    format_list(&self.present)
+                ),
+            });
+        }
+        check_cpu(cpu)?;
+        if !self.online.contains(&cpu) {
+            return Err(Error::CpuOffline { cpu });
+        }
+        if let Some((file, cpus)) = &self.cgroup {
+            if !cpus.contains(&cpu) {
+                return Err(Error::CpuUnusable {
+                    cpu,
+                    reason: format!(
+                        "outside the cgroup cpuset, {} allows {}",
+                        file.display(),
+                        format_list(cpus)
+                    ),
+                });
+            }
+        }
+        if let Some(affinity) = &self.affinity {
+            if !affinity.contains(&cpu) {
+                return Err(Error::CpuUnusable {
+                    cpu,
+                    reason: format!(
+                        "outside the affinity mask the process was started with ({}), e.g. by taskset or numactl",
+                        format_list(affinity)
+                    ),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    //> online cpus neither the cgroup cpuset nor the inherited affinity excludes
+    pub fn cpus(&self) -> impl Iterator<Item = usize> + '_ {
+        self.online.iter().copied().filter(|cpu| {
+            self.cgroup
+                .as_ref()
+                .is_none_or(|(_, cpus)| cpus.contains(cpu))
+                && self.affinity.as_ref().is_none_or(|cpus| cpus.contains(cpu))
+        })
+    }
+
+    //> split `cpus` into the usable ones and the reasons the rest are not
+    pub fn partition(&self, cpus: Vec<usize>) -> (Vec<usize>, Vec<Error>) {
+        let mut usable = Vec::with_capacity(cpus.len());
+        let mut excluded = Vec::new();
+        for cpu in cpus {
+            match self.check(cpu) {
+                Ok(()) => usable.push(cpu),
+                Err(e) => excluded.push(e),
+            }
+        }
+        (usable, excluded)
+    }
+}
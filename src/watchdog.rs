@@ -0,0 +1,150 @@
+use crate::{affinity::set_current_thread_affinity, lines::Lines, signal, usable::UsableCpus};
+use std::{
+    fmt,
+    sync::{
+        atomic::Ordering,
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+//> how often the watchdog looks at signals, the deadline and progress
+const TICK: Duration = Duration::from_millis(10);
+
+//> why a measurement ended before its iterations were done
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StopReason {
+    Timeout,
+    Interrupted { signal: i32 },
+    //> the counters stopped moving, e.g. the partner was descheduled for good
+    Stalled,
+}
+
+impl StopReason {
+    //> stable name for machine readable output
+    pub fn label(self) -> &'static str {
+        match self {
+            StopReason::Timeout => "timeout",
+            StopReason::Interrupted { .. } => "interrupted",
+            StopReason::Stalled => "stalled",
+        }
+    }
+
+    //> signals fit in 7 bits, the high bit marks an interrupt
+    fn encode(self) -> u8 {
+        match self {
+            StopReason::Timeout => 1,
+            StopReason::Stalled => 2,
+            StopReason::Interrupted { signal } => 0x80 | signal as u8,
+        }
+    }
+
+    fn decode(code: u8) -> Option<Self> {
+        match code {
+            1 => Some(StopReason::Timeout),
+            2 => Some(StopReason::Stalled),
+            code if code & 0x80 != 0 => Some(StopReason::Interrupted {
+                signal: (code & 0x7f) as i32,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StopReason::Timeout => write!(f, "timeout"),
+            StopReason::Interrupted { signal } => {
+                write!(f, "interrupted by {}", signal::name(*signal))
+            }
+            StopReason::Stalled => write!(f, "partner stalled"),
+        }
+    }
+}
+
+//> record why and raise the stop flag both hot loops poll
+pub fn raise(lines: &Lines, reason: StopReason) {
+    lines
+        .stop_reason()
+        .store(reason.encode(), Ordering::Relaxed);
+    lines.stop().store(true, Ordering::Release);
+}
+
+pub fn reason(lines: &Lines) -> Option<StopReason> {
+    if !lines.stop().load(Ordering::Acquire) {
+        return None;
+    }
+    StopReason::decode(lines.stop_reason().load(Ordering::Relaxed))
+}
+
+//> sleeps on a core outside the measured pair and stops the exchange on a
+//> termination signal, at the deadline, or when s2 has not moved for `stall`
+pub struct Watchdog {
+    cancel: mpsc::Sender<()>,
+    handle: thread::JoinHandle<()>,
+    //> None when it could not leave the cpu of the thread that spawned it
+    cpu: Option<usize>,
+    //> signals are only caught while someone is watching for them
+    _diversion: signal::Diversion,
+}
+
+impl Watchdog {
+    //> `timeout` None leaves the deadline to the hot loops themselves. The
+    //> thread inherits the affinity of its pinned parent, so it moves to the
+    //> first cpu outside `pair` that `usable` allows, if there is one
+    pub fn spawn(
+        lines: Arc<Lines>,
+        timeout: Option<Duration>,
+        stall: Duration,
+        pair: [usize; 2],
+        usable: &UsableCpus,
+    ) -> Self {
+        let diversion = signal::divert();
+        let target = usable.cpus().find(|cpu| !pair.contains(cpu));
+        let (cancel, cancelled) = mpsc::channel::<()>();
+        let (pinned, placed) = mpsc::sync_channel(1);
+        let handle = thread::spawn(move || {
+            let cpu = target.filter(|&cpu| set_current_thread_affinity("watchdog", cpu).is_ok());
+            let _ = pinned.send(cpu);
+
+            let start = Instant::now();
+            let mut seen = lines.s2().load(Ordering::Relaxed);
+            let mut progress = start;
+            while let Err(RecvTimeoutError::Timeout) = cancelled.recv_timeout(TICK) {
+                if let Some(signal) = signal::received() {
+                    return raise(&lines, StopReason::Interrupted { signal });
+                }
+                if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+                    return raise(&lines, StopReason::Timeout);
+                }
+                //> one read of s2 per tick, a single extra miss every 10ms
+                let current = lines.s2().load(Ordering::Relaxed);
+                if current != seen {
+                    seen = current;
+                    progress = Instant::now();
+                } else if progress.elapsed() >= stall {
+                    return raise(&lines, StopReason::Stalled);
+                }
+            }
+        });
+        Self {
+            cancel,
+            handle,
+            cpu: placed.recv().ok().flatten(),
+            _diversion: diversion,
+        }
+    }
+
+    //> the cpu it sleeps on, None when it shares the spawning thread's cpu
+    pub fn cpu(&self) -> Option<usize> {
+        self.cpu
+    }
+
+    pub fn cancel(self) {
+        drop(self.cancel);
+        let _ = self.handle.join();
+    }
+}